@@ -1,13 +1,21 @@
 use bevy::prelude::*;
 use bevy_kira_audio::{Audio, AudioSource};
 
+use crate::{
+    base::{BaseDestroyed, BaseHit},
+    enemy::EnemyDestroyed,
+    projectile::EnemyHit,
+    tower::{TowerChanged, TowerShot},
+};
+
 pub struct AudioPlugin;
 
 impl Plugin for AudioPlugin {
     fn build(&self, app: &mut App) {
         app.insert_resource(GlobalVolume(0))
             .add_startup_system(audio_setup)
-            .add_system(volume_change);
+            .add_system(volume_change)
+            .add_system(sound_play);
     }
 }
 
@@ -40,3 +48,30 @@ fn volume_change(volume: Res<GlobalVolume>, audio: Res<Audio>) {
         audio.set_volume(volume.0 as f32 / 100.0);
     }
 }
+
+/// Plays a sound for everything that happened in the game since the last
+/// frame.
+fn sound_play(
+    audio: Res<Audio>,
+    sounds: Res<AudioHandleMap>,
+    mut base_hit_events: EventReader<BaseHit>,
+    mut base_destroyed_events: EventReader<BaseDestroyed>,
+    mut enemy_destroyed_events: EventReader<EnemyDestroyed>,
+    mut enemy_hit_events: EventReader<EnemyHit>,
+    mut tower_changed_events: EventReader<TowerChanged>,
+    mut tower_shot_events: EventReader<TowerShot>,
+) {
+    let plays = [
+        (base_hit_events.iter().count(), &sounds.base_hit),
+        (base_destroyed_events.iter().count(), &sounds.base_destroy),
+        (enemy_destroyed_events.iter().count(), &sounds.enemy_destroy),
+        (enemy_hit_events.iter().count(), &sounds.enemy_hit),
+        (tower_changed_events.iter().count(), &sounds.tower_place),
+        (tower_shot_events.iter().count(), &sounds.tower_shoot),
+    ];
+    for (count, sound) in plays {
+        for _ in 0..count {
+            audio.play(sound.clone());
+        }
+    }
+}
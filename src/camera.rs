@@ -0,0 +1,220 @@
+use bevy::{
+    input::mouse::{MouseMotion, MouseScrollUnit, MouseWheel},
+    prelude::*,
+};
+use bevy_egui::{EguiContext, EguiSystem};
+
+use crate::{
+    coord::{Coord, HALF_CELL_SIZE},
+    tile_map::{Bounds, TileMap},
+};
+
+pub struct CameraPlugin;
+
+impl Plugin for CameraPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CursorPosition>()
+            .init_resource::<CameraBounds>()
+            .add_startup_system(camera_setup)
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                cursor_update.after(EguiSystem::BeginFrame),
+            )
+            .add_system(camera_fit)
+            .add_system(camera_control);
+    }
+}
+
+/// Smallest and largest camera scale. Smaller scales zoom in.
+const MIN_SCALE: f32 = 0.5;
+const MAX_SCALE: f32 = 2.0;
+/// Scale change per line scrolled.
+const ZOOM_STEP: f32 = 1.1;
+/// Keyboard panning speed in screen pixels per second.
+const PAN_SPEED: f32 = 500.0;
+
+/// The camera showing the map, as opposed to the UI camera.
+#[derive(Component)]
+pub struct MainCamera;
+
+/// World position under the cursor, through the main camera. `None` while the
+/// cursor is outside of the window or over the UI, so that clicks and scrolls
+/// meant for the UI do not reach the map.
+#[derive(Default)]
+pub struct CursorPosition(pub Option<Vec2>);
+
+impl CursorPosition {
+    /// The cell under the cursor, whether or not it is part of a map.
+    pub fn coord(&self) -> Option<Coord> {
+        self.0.map(Coord::from_world)
+    }
+}
+
+/// Cells the camera may look at. The center of the view is kept within them.
+#[derive(Default)]
+pub struct CameraBounds(pub Option<Bounds>);
+
+fn camera_setup(mut commands: Commands) {
+    commands
+        .spawn_bundle(OrthographicCameraBundle::new_2d())
+        .insert(MainCamera);
+}
+
+fn cursor_update(
+    mut cursor: ResMut<CursorPosition>,
+    mut egui_ctx: ResMut<EguiContext>,
+    windows: Res<Windows>,
+    query: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+) {
+    let ctx = egui_ctx.ctx_mut();
+    if ctx.wants_pointer_input() || ctx.is_pointer_over_area() {
+        if cursor.0.is_some() {
+            cursor.0 = None;
+        }
+        return;
+    }
+    let position = match (windows.get_primary(), query.get_single()) {
+        (Some(window), Ok((camera, transform))) => window.cursor_position().map(|screen| {
+            let size = Vec2::new(window.width(), window.height());
+            screen_to_world(screen, size, camera.projection_matrix, transform)
+        }),
+        _ => None,
+    };
+    if cursor.0 != position {
+        cursor.0 = position;
+    }
+}
+
+/// The world position shown at `screen`, in pixels from the bottom left corner
+/// of a window of `size`.
+fn screen_to_world(
+    screen: Vec2,
+    size: Vec2,
+    projection: Mat4,
+    transform: &GlobalTransform,
+) -> Vec2 {
+    let ndc = screen / size * 2.0 - Vec2::ONE;
+    let ndc_to_world = transform.compute_matrix() * projection.inverse();
+    ndc_to_world.project_point3(ndc.extend(-1.0)).truncate()
+}
+
+/// Centers a newly set up map and zooms out until it fits the window, if the
+/// zoom limits allow.
+fn camera_fit(
+    mut bounds: ResMut<CameraBounds>,
+    windows: Res<Windows>,
+    tile_map: Option<Res<TileMap>>,
+    mut query: Query<(&mut Transform, &mut OrthographicProjection), With<MainCamera>>,
+) {
+    let (tile_map, window) = match (tile_map, windows.get_primary()) {
+        (Some(tile_map), Some(window)) if tile_map.is_changed() => (tile_map, window),
+        _ => return,
+    };
+    bounds.0 = Some(tile_map.bounds());
+
+    let (min, max) = world_rect(tile_map.bounds());
+    for (mut transform, mut projection) in query.iter_mut() {
+        let size = max - min;
+        let scale = (size.x / window.width()).max(size.y / window.height());
+        projection.scale = scale.clamp(MIN_SCALE, MAX_SCALE).max(1.0);
+        let center = (min + max) * 0.5;
+        transform.translation = center.extend(transform.translation.z);
+    }
+}
+
+/// Pans with WASD, the arrow keys or by dragging with the middle mouse button,
+/// and zooms towards the cursor with the scroll wheel.
+fn camera_control(
+    mut egui_ctx: ResMut<EguiContext>,
+    mut motion_events: EventReader<MouseMotion>,
+    mut wheel_events: EventReader<MouseWheel>,
+    time: Res<Time>,
+    keys: Res<Input<KeyCode>>,
+    buttons: Res<Input<MouseButton>>,
+    cursor: Res<CursorPosition>,
+    bounds: Res<CameraBounds>,
+    mut query: Query<(&mut Transform, &mut OrthographicProjection), With<MainCamera>>,
+) {
+    let (mut transform, mut projection) = match query.get_single_mut() {
+        Ok(camera) => camera,
+        Err(_) => return,
+    };
+    let mut center = transform.translation.truncate();
+    let ctx = egui_ctx.ctx_mut();
+
+    let mut direction = Vec2::ZERO;
+    if !ctx.wants_keyboard_input() {
+        for (keys_for, offset) in [
+            ([KeyCode::W, KeyCode::Up], Vec2::Y),
+            ([KeyCode::S, KeyCode::Down], -Vec2::Y),
+            ([KeyCode::A, KeyCode::Left], -Vec2::X),
+            ([KeyCode::D, KeyCode::Right], Vec2::X),
+        ] {
+            if keys.any_pressed(keys_for) {
+                direction += offset;
+            }
+        }
+    }
+    center += direction * PAN_SPEED * projection.scale * time.delta_seconds();
+
+    let dragging = buttons.pressed(MouseButton::Middle);
+    for event in motion_events.iter() {
+        if dragging {
+            // Window coordinates grow downwards.
+            center += Vec2::new(-event.delta.x, event.delta.y) * projection.scale;
+        }
+    }
+
+    for event in wheel_events.iter() {
+        // Scrolling over the UI scrolls the UI instead.
+        let position = match cursor.0 {
+            Some(position) => position,
+            None => continue,
+        };
+        let lines = match event.unit {
+            MouseScrollUnit::Line => event.y,
+            MouseScrollUnit::Pixel => event.y / 100.0,
+        };
+        let scale = (projection.scale * ZOOM_STEP.powf(-lines)).clamp(MIN_SCALE, MAX_SCALE);
+        // Keep the point under the cursor in place.
+        center += (position - center) * (1.0 - scale / projection.scale);
+        projection.scale = scale;
+    }
+
+    if let Some(bounds) = bounds.0 {
+        let (min, max) = world_rect(bounds);
+        center = center.clamp(min, max);
+    }
+    if center != transform.translation.truncate() {
+        transform.translation = center.extend(transform.translation.z);
+    }
+}
+
+/// The world space corners of the outer edges of `bounds`.
+fn world_rect(bounds: Bounds) -> (Vec2, Vec2) {
+    (
+        Vec2::from(bounds.min) - HALF_CELL_SIZE,
+        Vec2::from(bounds.max) + HALF_CELL_SIZE,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::render::camera::CameraProjection;
+
+    #[test]
+    fn picks_through_a_panned_and_zoomed_camera() {
+        let size = Vec2::new(800.0, 600.0);
+        let mut projection = OrthographicCameraBundle::new_2d().orthographic_projection;
+        projection.scale = 2.0;
+        projection.update(size.x, size.y);
+        let transform = GlobalTransform::from_xyz(100.0, -50.0, 999.9);
+
+        let world =
+            |screen| screen_to_world(screen, size, projection.get_projection_matrix(), &transform);
+        assert!(world(size * 0.5).abs_diff_eq(Vec2::new(100.0, -50.0), 1e-3));
+        assert!(world(Vec2::ZERO).abs_diff_eq(Vec2::new(-700.0, -650.0), 1e-3));
+        assert!(world(size).abs_diff_eq(Vec2::new(900.0, 550.0), 1e-3));
+    }
+}
@@ -0,0 +1,149 @@
+use bevy::prelude::*;
+use bevy_egui::EguiContext;
+use iyes_loopless::prelude::*;
+
+use crate::game_state::GameState;
+
+pub struct ClockPlugin;
+
+impl Plugin for ClockPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(clock_hotkeys);
+    }
+}
+
+/// Game speeds the player can pick.
+pub const SPEEDS: [f64; 3] = [1.0, 2.0, 3.0];
+
+/// Game time covered by a single simulation tick, whatever the frame rate or
+/// the speed.
+pub const TICK_SECONDS: f64 = 1.0 / 60.0;
+
+/// Time in the game world. It only advances in whole ticks while playing, at
+/// `speed` times real time, so every gameplay timer freezes, slows down and
+/// speeds up together. Gameplay systems must use it instead of [`Time`].
+pub struct GameClock {
+    elapsed: f64,
+    delta: f64,
+    pub speed: f64,
+    /// Game time owed to the simulation that does not make up a whole tick
+    /// yet.
+    accumulator: f64,
+    /// Whether a single tick was asked for while paused.
+    stepping: bool,
+}
+
+impl Default for GameClock {
+    fn default() -> Self {
+        Self {
+            elapsed: 0.0,
+            delta: 0.0,
+            speed: 1.0,
+            accumulator: 0.0,
+            stepping: false,
+        }
+    }
+}
+
+impl GameClock {
+    /// Seconds of game time since the map was set up.
+    pub fn elapsed(&self) -> f64 {
+        self.elapsed
+    }
+
+    /// Seconds of game time since the last tick.
+    pub fn delta_seconds(&self) -> f32 {
+        self.delta as f32
+    }
+
+    /// Number of ticks to run after `real_delta` seconds of real time. The
+    /// remainder is carried over to the next call.
+    pub fn due_ticks(&mut self, real_delta: f64) -> u32 {
+        self.accumulator += real_delta * self.speed;
+        // Leeway for rounding, so that exactly one tick's worth of time is not
+        // counted as slightly less.
+        let ticks = ((self.accumulator + 1e-9) / TICK_SECONDS).floor();
+        self.accumulator -= ticks * TICK_SECONDS;
+        ticks as u32
+    }
+
+    /// Advances the clock by exactly `delta` seconds of game time, whatever
+    /// the speed.
+    pub fn advance(&mut self, delta: f64) {
+        self.delta = delta;
+        self.elapsed += delta;
+    }
+
+    /// Starts over from zero, keeping the speed.
+    pub fn reset(&mut self) {
+        self.elapsed = 0.0;
+        self.delta = 0.0;
+        self.accumulator = 0.0;
+        self.stepping = false;
+    }
+
+    /// Runs a single tick while [`GameState::Paused`].
+    pub fn step(&mut self) {
+        self.stepping = true;
+    }
+
+    /// Whether a step was asked for since the last call.
+    pub fn take_step(&mut self) -> bool {
+        std::mem::take(&mut self.stepping)
+    }
+}
+
+/// Space pauses and resumes, Tab cycles through the speeds and N steps a
+/// single tick while paused.
+fn clock_hotkeys(
+    mut commands: Commands,
+    mut egui_ctx: ResMut<EguiContext>,
+    mut clock: ResMut<GameClock>,
+    keys: Res<Input<KeyCode>>,
+    game_state: Res<CurrentState<GameState>>,
+) {
+    if egui_ctx.ctx_mut().wants_keyboard_input() {
+        return;
+    }
+    if keys.just_pressed(KeyCode::Tab) {
+        let next = SPEEDS
+            .iter()
+            .position(|&speed| speed == clock.speed)
+            .map_or(0, |i| i + 1);
+        clock.speed = SPEEDS[next % SPEEDS.len()];
+    }
+    match game_state.0 {
+        GameState::Playing if keys.just_pressed(KeyCode::Space) => {
+            commands.insert_resource(NextState(GameState::Paused));
+        }
+        GameState::Paused if keys.just_pressed(KeyCode::Space) => {
+            commands.insert_resource(NextState(GameState::Playing));
+        }
+        GameState::Paused if keys.just_pressed(KeyCode::N) => clock.step(),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runs_at_its_speed() {
+        let mut clock = GameClock::default();
+        assert_eq!(clock.due_ticks(0.5), 30);
+        assert_eq!(clock.due_ticks(TICK_SECONDS / 2.0), 0);
+        assert_eq!(clock.due_ticks(TICK_SECONDS / 2.0), 1);
+        clock.speed = 3.0;
+        assert_eq!(clock.due_ticks(0.5), 90);
+
+        clock.advance(0.25);
+        clock.advance(0.25);
+        assert_eq!(clock.elapsed(), 0.5);
+        assert_eq!(clock.delta_seconds(), 0.25);
+
+        clock.reset();
+        assert_eq!(clock.elapsed(), 0.0);
+        assert_eq!(clock.speed, 3.0);
+    }
+}
@@ -1,9 +1,14 @@
 use bevy::math::Vec2;
+use serde::{Deserialize, Serialize};
+
+use std::fmt;
 
 pub const CELL_SIZE: f32 = 32.0;
 pub const HALF_CELL_SIZE: f32 = CELL_SIZE * 0.5;
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// Grid coordinate. Serialized as an `(x, y)` tuple to keep data files terse.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "(i32, i32)", into = "(i32, i32)")]
 pub struct Coord {
     pub x: i32,
     pub y: i32,
@@ -15,6 +20,34 @@ impl Coord {
     }
 }
 
+impl Coord {
+    /// The cell containing a world position.
+    pub fn from_world(position: Vec2) -> Self {
+        Self::new(
+            ((position.x + HALF_CELL_SIZE) / CELL_SIZE).floor() as i32,
+            ((position.y + HALF_CELL_SIZE) / CELL_SIZE).floor() as i32,
+        )
+    }
+}
+
+impl From<(i32, i32)> for Coord {
+    fn from((x, y): (i32, i32)) -> Self {
+        Self::new(x, y)
+    }
+}
+
+impl From<Coord> for (i32, i32) {
+    fn from(coord: Coord) -> Self {
+        (coord.x, coord.y)
+    }
+}
+
+impl fmt::Display for Coord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "({}, {})", self.x, self.y)
+    }
+}
+
 impl From<Coord> for Vec2 {
     fn from(coord: Coord) -> Self {
         Self::new(coord.x as f32 * CELL_SIZE, coord.y as f32 * CELL_SIZE)
@@ -0,0 +1,396 @@
+use bevy::{
+    input::{mouse::MouseButtonInput, ElementState},
+    prelude::*,
+    sprite::Mesh2dHandle,
+};
+use iyes_loopless::prelude::*;
+
+use std::num::NonZeroU32;
+
+use crate::{
+    camera::{CameraBounds, CursorPosition},
+    coord::{Coord, CELL_SIZE},
+    game_state::GameState,
+    map::{save_map, Lane, Map, MapHandle, MapSource},
+    mesh::{MeshMaterial, RegPoly, Track},
+    path::BranchChoice,
+    validation::{validate_map, MapProblem},
+    wave::Wave,
+};
+
+pub struct EditorPlugin;
+
+impl Plugin for EditorPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<EditorAction>()
+            .init_resource::<Editor>()
+            .add_startup_system(editor_setup)
+            .add_enter_system(GameState::Editor, editor_enter)
+            .add_exit_system(GameState::Editor, editor_exit)
+            .add_system(
+                editor_open
+                    .run_not_in_state(GameState::MainMenu)
+                    .run_not_in_state(GameState::Editor)
+                    .run_not_in_state(GameState::LoadingMap),
+            )
+            .add_system_set(
+                ConditionSet::new()
+                    .run_in_state(GameState::Editor)
+                    .with_system(editor_click)
+                    .with_system(editor_keys)
+                    .with_system(editor_action)
+                    .with_system(editor_draw)
+                    .into(),
+            );
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EditorTool {
+    #[default]
+    Path,
+    BuildSpot,
+    Spawner,
+    Base,
+}
+
+impl EditorTool {
+    pub const ALL: [EditorTool; 4] = [
+        EditorTool::Path,
+        EditorTool::BuildSpot,
+        EditorTool::Spawner,
+        EditorTool::Base,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            EditorTool::Path => "Path",
+            EditorTool::BuildSpot => "Build spot",
+            EditorTool::Spawner => "Spawner",
+            EditorTool::Base => "Base",
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Editor {
+    pub tool: EditorTool,
+    /// Index of the lane whose path the path tool edits.
+    pub lane: usize,
+    pub problems: Vec<MapProblem>,
+    /// Result of the last save, shown in the editor panel.
+    pub message: Option<String>,
+}
+
+/// The map being edited. Only present in the editor.
+pub struct EditorMap(pub Map);
+
+pub enum EditorAction {
+    Save,
+    /// Save, then play the map.
+    PlayTest,
+    /// Leave the editor without saving.
+    Discard,
+}
+
+#[derive(Component)]
+struct EditorEntity;
+
+/// Cells beyond the edges of the map that the camera can pan to.
+const EDITOR_MARGIN: i32 = 10;
+
+struct EditorAssets {
+    node: Mesh2dHandle,
+    marker: Mesh2dHandle,
+    build_spot: MeshMaterial,
+    track: Handle<ColorMaterial>,
+    selected_track: Handle<ColorMaterial>,
+    spawner: Handle<ColorMaterial>,
+    base: Handle<ColorMaterial>,
+    problem: MeshMaterial,
+}
+
+fn editor_setup(
+    mut commands: Commands,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    commands.insert_resource(EditorAssets {
+        node: Mesh2dHandle(meshes.add(RegPoly::fill(8, 4.0).into())),
+        marker: Mesh2dHandle(meshes.add(RegPoly::fill(6, 13.0).into())),
+        build_spot: MeshMaterial {
+            mesh: Mesh2dHandle(meshes.add(shape::Quad::new(Vec2::new(30.0, 30.0)).into())),
+            material: materials.add(Color::rgb(0.3, 0.3, 0.3).into()),
+        },
+        track: materials.add(Color::rgb(0.22, 0.2, 0.17).into()),
+        selected_track: materials.add(Color::rgb(0.36, 0.33, 0.27).into()),
+        spawner: materials.add(Color::rgb(0.4, 0.2, 0.6).into()),
+        base: materials.add(Color::rgb(6.0, 0.6, 0.2).into()),
+        problem: MeshMaterial {
+            mesh: Mesh2dHandle(meshes.add(RegPoly::outline(4, 14.0).into())),
+            material: materials.add(Color::rgb(1.0, 0.1, 0.1).into()),
+        },
+    });
+}
+
+fn editor_open(mut commands: Commands, keys: Res<Input<KeyCode>>) {
+    if keys.just_pressed(KeyCode::F2) {
+        commands.insert_resource(NextState(GameState::Editor));
+    }
+}
+
+/// Starts editing the current map, or a blank one if there is none.
+fn editor_enter(
+    mut commands: Commands,
+    mut editor: ResMut<Editor>,
+    maps: Res<Assets<Map>>,
+    map_handle: Option<Res<MapHandle>>,
+) {
+    let map = map_handle
+        .and_then(|map_handle| maps.get(&map_handle.0).cloned())
+        .unwrap_or_else(|| Map {
+            name: "Untitled".to_string(),
+            difficulty: default(),
+            starting_coins: 10,
+            sell_refund: default(),
+            base: Coord::new(0, 0),
+            lanes: Vec::new(),
+            build_spots: Vec::new(),
+            open_field: None,
+            terrain: Vec::new(),
+        });
+
+    *editor = Editor {
+        problems: validate_map(&map),
+        ..default()
+    };
+    commands.insert_resource(EditorMap(map));
+}
+
+fn editor_exit(mut commands: Commands, query: Query<Entity, With<EditorEntity>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    commands.remove_resource::<EditorMap>();
+}
+
+/// Waves for a new lane: the same as the first lane's, so that lanes stay in
+/// step.
+fn new_lane_waves(map: &Map) -> Vec<Wave> {
+    match map.lanes.first() {
+        Some(lane) => lane.waves.clone(),
+        None => vec![Wave {
+            enemy: "grunt".to_string(),
+            count: NonZeroU32::new(5).unwrap(),
+            spacing: 1.5,
+            delay: 0.0,
+        }],
+    }
+}
+
+/// Left clicks add to the map with the current tool, right clicks remove.
+fn editor_click(
+    mut editor: ResMut<Editor>,
+    mut editor_map: ResMut<EditorMap>,
+    mut mouse_events: EventReader<MouseButtonInput>,
+    cursor: Res<CursorPosition>,
+) {
+    for mouse_event in mouse_events.iter() {
+        if mouse_event.state != ElementState::Pressed {
+            continue;
+        }
+        let coord = match cursor.coord() {
+            Some(coord) => coord,
+            None => continue,
+        };
+        let add = match mouse_event.button {
+            MouseButton::Left => true,
+            MouseButton::Right => false,
+            _ => continue,
+        };
+
+        let map = &mut editor_map.0;
+        match editor.tool {
+            EditorTool::Path => {
+                let lane = match map.lanes.get_mut(editor.lane) {
+                    Some(lane) => lane,
+                    None => {
+                        editor.message = Some("Place a spawner first".to_string());
+                        continue;
+                    }
+                };
+                if add {
+                    if lane.path.last() != Some(&coord) {
+                        lane.path.push(coord);
+                    }
+                } else if let Some(index) = lane.path.iter().rposition(|&node| node == coord) {
+                    // The first node is the spawner, which has its own tool.
+                    if index > 0 {
+                        lane.path.remove(index);
+                    }
+                }
+            }
+            EditorTool::BuildSpot => {
+                map.build_spots.retain(|&build_spot| build_spot != coord);
+                if add {
+                    map.build_spots.push(coord);
+                }
+            }
+            EditorTool::Spawner => {
+                if add {
+                    let waves = new_lane_waves(map);
+                    map.lanes.push(Lane {
+                        path: vec![coord],
+                        branches: Vec::new(),
+                        choice: BranchChoice::default(),
+                        waves,
+                    });
+                    editor.lane = map.lanes.len() - 1;
+                } else {
+                    map.lanes.retain(|lane| lane.path.first() != Some(&coord));
+                    editor.lane = editor.lane.min(map.lanes.len().saturating_sub(1));
+                }
+            }
+            EditorTool::Base => {
+                if add {
+                    map.base = coord;
+                }
+            }
+        }
+        editor.problems = validate_map(map);
+    }
+}
+
+fn editor_keys(keys: Res<Input<KeyCode>>, mut events: EventWriter<EditorAction>) {
+    if keys.just_pressed(KeyCode::F5) {
+        events.send(EditorAction::PlayTest);
+    }
+    if keys.just_pressed(KeyCode::F2) {
+        events.send(EditorAction::Discard);
+    }
+}
+
+fn editor_action(
+    mut commands: Commands,
+    mut editor: ResMut<Editor>,
+    mut events: EventReader<EditorAction>,
+    mut maps: ResMut<Assets<Map>>,
+    mut source: ResMut<MapSource>,
+    editor_map: Res<EditorMap>,
+    asset_server: Res<AssetServer>,
+) {
+    for event in events.iter() {
+        if let EditorAction::Discard = event {
+            commands.insert_resource(NextState(GameState::LoadingMap));
+            continue;
+        }
+
+        editor.problems = validate_map(&editor_map.0);
+        if !editor.problems.is_empty() {
+            editor.message = Some("Fix the problems before saving".to_string());
+            continue;
+        }
+        let path = source.path().to_string();
+        match save_map(&editor_map.0, &path) {
+            Ok(()) => editor.message = Some(format!("Saved to {}", path)),
+            Err(err) => {
+                error!("Could not save map '{}': {}", path, err);
+                editor.message = Some(format!("Could not save: {}", err));
+                continue;
+            }
+        }
+
+        // Replace the loaded map right away rather than waiting for the asset
+        // server to notice the new file. A generated map is played from its
+        // file from now on.
+        let handle: Handle<Map> = asset_server.load(path.as_str());
+        maps.set_untracked(&handle, editor_map.0.clone());
+        *source = MapSource::File(path);
+
+        if let EditorAction::PlayTest = event {
+            commands.insert_resource(NextState(GameState::LoadingMap));
+        }
+    }
+}
+
+/// Redraws the whole map whenever it has been edited.
+fn editor_draw(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    assets: Res<EditorAssets>,
+    editor: Res<Editor>,
+    editor_map: Res<EditorMap>,
+    mut camera_bounds: ResMut<CameraBounds>,
+    query: Query<Entity, With<EditorEntity>>,
+) {
+    if !editor_map.is_changed() && !editor.is_changed() {
+        return;
+    }
+    // Leave room around the map to build it out.
+    camera_bounds.0 = Some(editor_map.0.tile_map().bounds().grow(EDITOR_MARGIN));
+
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    let map = &editor_map.0;
+    let mut spawn = |mesh: &Mesh2dHandle, material: &Handle<ColorMaterial>, position: Vec3| {
+        commands
+            .spawn_bundle(ColorMesh2dBundle {
+                mesh: mesh.clone(),
+                material: material.clone(),
+                transform: Transform::from_translation(position),
+                ..Default::default()
+            })
+            .insert(EditorEntity);
+    };
+
+    for &build_spot in &map.build_spots {
+        let position = Vec2::from(build_spot).extend(0.0);
+        spawn(
+            &assets.build_spot.mesh,
+            &assets.build_spot.material,
+            position,
+        );
+    }
+    for (i, lane) in map.lanes.iter().enumerate() {
+        let material = if i == editor.lane {
+            &assets.selected_track
+        } else {
+            &assets.track
+        };
+        let track = Track {
+            segments: lane
+                .path
+                .windows(2)
+                .map(|segment| (segment[0].into(), segment[1].into()))
+                .collect(),
+            width: CELL_SIZE * 0.75,
+        };
+        let track = Mesh2dHandle(meshes.add(track.into()));
+        spawn(&track, material, Vec3::new(0.0, 0.0, 0.1));
+        for &node in lane.path.iter().skip(1) {
+            spawn(&assets.node, &assets.spawner, Vec2::from(node).extend(0.2));
+        }
+        if let Some(&spawner) = lane.path.first() {
+            spawn(
+                &assets.marker,
+                &assets.spawner,
+                Vec2::from(spawner).extend(1.0),
+            );
+        }
+    }
+    spawn(
+        &assets.marker,
+        &assets.base,
+        Vec2::from(map.base).extend(1.0),
+    );
+
+    for position in editor.problems.iter().filter_map(MapProblem::position) {
+        spawn(
+            &assets.problem.mesh,
+            &assets.problem.material,
+            Vec2::from(position).extend(2.0),
+        );
+    }
+}
@@ -1,213 +1,249 @@
-use bevy::{prelude::*, sprite::Mesh2dHandle};
-use bevy_kira_audio::Audio;
-use iyes_loopless::prelude::*;
+use bevy::prelude::*;
+use rand::Rng;
+
+use std::sync::Arc;
 
 use crate::{
-    audio::AudioHandleMap,
-    base::Base,
-    coord::{Coord, CELL_SIZE},
+    base::{Base, BaseHit},
+    clock::GameClock,
+    coord::{Coord, CELL_SIZE, HALF_CELL_SIZE},
     currency::Currency,
-    game_state::GameState,
+    enemy_kind::EnemyKinds,
     health::Health,
-    mesh::{MeshMaterial, RegPoly},
+    map::MapEntity,
+    path::{choose_branch, Path, PathPosition},
+    simulation::{SimulationAppExt, SimulationPhase, SimulationRng},
+    stats::GameStats,
+    tile_map::{Terrain, TileMap},
+    tower::Tower,
+    tower_kind::TowerStats,
+    wave::{CurrentWave, Wave},
 };
 
 pub struct EnemyPlugin;
 
 impl Plugin for EnemyPlugin {
     fn build(&self, app: &mut App) {
-        app.add_event::<SpawnEnemySpawner>()
-            .add_startup_system(enemy_setup)
-            .add_system_set(
-                ConditionSet::new()
-                    .run_in_state(GameState::Playing)
-                    .with_system(enemy_spawn)
-                    .with_system(enemy_spawner_spawn)
-                    .with_system(enemy_path_follow)
-                    // TODO: This should probably have a deterministic ordering
-                    .with_system(play_time_update.run_in_state(GameState::Playing))
-                    .into(),
-            )
-            .add_system_to_stage(
-                CoreStage::PostUpdate,
-                enemy_destroy.run_in_state(GameState::Playing),
-            );
+        app.add_simulation_event::<SpawnEnemySpawner>()
+            .add_event::<EnemyDestroyed>()
+            .add_simulation_system(SimulationPhase::Input, enemy_spawner_spawn)
+            .add_simulation_system(SimulationPhase::Spawn, enemy_spawn)
+            .add_simulation_system(SimulationPhase::Move, enemy_path_follow)
+            .add_simulation_system(SimulationPhase::Cleanup, enemy_destroy);
     }
 }
 
+/// Above the track and other map overlays.
+const ENEMY_Z: f32 = 0.5;
+
 #[derive(Component)]
-pub struct Enemy;
+pub struct Enemy {
+    /// Index into [`EnemyKinds`].
+    pub kind: usize,
+    /// Coins paid out when the enemy is destroyed.
+    pub bounty: i32,
+    /// Damage dealt to the base when the enemy reaches it.
+    pub damage: i32,
+    pub radius: f32,
+}
+
+/// Sent when an enemy runs out of health.
+pub struct EnemyDestroyed;
 
 fn enemy_destroy(
     mut commands: Commands,
     mut currency: ResMut<Currency>,
-    sounds: Res<AudioHandleMap>,
-    audio: Res<Audio>,
-    query: Query<(Entity, &Health), (With<Enemy>, Changed<Health>)>,
+    mut stats: ResMut<GameStats>,
+    mut events: EventWriter<EnemyDestroyed>,
+    query: Query<(Entity, &Enemy, &Health), Changed<Health>>,
 ) {
-    for (entity, health) in query.iter() {
+    for (entity, enemy, health) in query.iter() {
         if health.current <= 0 {
-            currency.coins += 1;
-            audio.play(sounds.enemy_destroy.clone());
+            currency.coins += enemy.bounty;
+            stats.kills += 1;
+            stats.coins_earned += enemy.bounty;
+            events.send(EnemyDestroyed);
             commands.entity(entity).despawn_recursive();
         }
     }
 }
 
-#[derive(Deref)]
-struct EnemyAssets(MeshMaterial);
-
-#[derive(Deref)]
-struct EnemySpawnerAssets(MeshMaterial);
-
-fn enemy_setup(
-    mut commands: Commands,
-    mut materials: ResMut<Assets<ColorMaterial>>,
-    mut meshes: ResMut<Assets<Mesh>>,
-) {
-    commands.insert_resource(EnemyAssets(MeshMaterial {
-        mesh: Mesh2dHandle(meshes.add(RegPoly::fill(4, 12.0).into())),
-        material: materials.add(Color::rgb(1.0, 0.3, 0.0).into()),
-    }));
-
-    commands.insert_resource(EnemySpawnerAssets(MeshMaterial {
-        mesh: Mesh2dHandle(meshes.add(RegPoly::fill(6, 14.0).into())),
-        material: materials.add(Color::rgb(0.4, 0.2, 0.6).into()),
-    }));
-}
-
-pub struct Path {
-    nodes: Vec<Coord>,
-    segment_lengths: Vec<i32>,
+#[derive(Component)]
+pub struct PathFollow {
+    pub path: Arc<Path>,
+    pub position: PathPosition,
+    /// Speed in world units per second on terrain that does not slow down.
+    pub speed: f32,
+    /// Current velocity along the path, in world units per second.
+    pub velocity: Vec2,
 }
 
-impl Path {
-    pub fn new(nodes: Vec<Coord>) -> Path {
-        let segment_lengths = nodes
-            .windows(2)
-            .map(|segment| {
-                (segment[1].x - segment[0].x).abs() + (segment[1].y - segment[0].y).abs()
-            })
-            .collect();
-
-        Self {
-            nodes,
-            segment_lengths,
-        }
-    }
-
-    fn lerp(&self, progress: f32) -> Vec2 {
-        let mut tile_progress = (self.length() as f32 * CELL_SIZE) * progress;
-        for (i, segment) in self.nodes.windows(2).enumerate() {
-            let segment_length = self.segment_lengths[i] as f32 * CELL_SIZE;
-            if tile_progress > segment_length {
-                tile_progress -= segment_length;
-                continue;
-            }
-            let segment_progress = tile_progress / segment_length;
-            let segment_start: Vec2 = segment[0].into();
-            let segment_relative_end: Vec2 =
-                Coord::new(segment[1].x - segment[0].x, segment[1].y - segment[0].y).into();
-            return segment_start + segment_relative_end * segment_progress;
-        }
-        // Progress is outside of 0.0 - 1.0
-        Vec2::default()
-    }
-
-    fn length(&self) -> i32 {
-        self.segment_lengths.iter().fold(0, |acc, cur| acc + cur)
+impl PathFollow {
+    /// Distance left to walk before reaching the base, in world units.
+    pub fn remaining(&self) -> f32 {
+        self.path.remaining(&self.position)
     }
 }
 
-#[derive(Component)]
-struct PathFollow {
-    progress: f32,
+/// How much of the branch leaving `node` through `edge` is covered by towers.
+fn branch_defense(path: &Path, node: usize, edge: usize, towers: &[(Vec2, f32)]) -> f32 {
+    path.branch_points(node, edge, HALF_CELL_SIZE)
+        .iter()
+        .map(|&point| {
+            towers
+                .iter()
+                .filter(|(position, range)| position.distance_squared(point) <= range * range)
+                .count()
+        })
+        .sum::<usize>() as f32
 }
 
 fn enemy_path_follow(
     mut commands: Commands,
-    time: Res<Time>,
-    path: Res<Path>,
-    audio: Res<Audio>,
-    sounds: Res<AudioHandleMap>,
-    mut enemy_query: Query<(Entity, &mut Transform, &mut PathFollow)>,
+    clock: Res<GameClock>,
+    mut rng: ResMut<SimulationRng>,
+    mut base_hit_events: EventWriter<BaseHit>,
+    tile_map: Res<TileMap>,
+    mut enemy_query: Query<(Entity, &Enemy, &mut Transform, &mut PathFollow)>,
     mut base_query: Query<&mut Health, With<Base>>,
+    tower_query: Query<(&Transform, &TowerStats), (With<Tower>, Without<Enemy>)>,
 ) {
-    for (entity, mut transform, mut path_follow) in enemy_query.iter_mut() {
-        path_follow.progress += 0.025 * time.delta_seconds();
-        if path_follow.progress >= 1.0 {
+    let towers: Vec<(Vec2, f32)> = tower_query
+        .iter()
+        .map(|(transform, stats)| (transform.translation.truncate(), stats.range))
+        .collect();
+    let rng = &mut rng.0;
+
+    for (entity, enemy, mut transform, mut path_follow) in enemy_query.iter_mut() {
+        let path = Arc::clone(&path_follow.path);
+        let terrain = tile_map.get(Coord::from_world(transform.translation.truncate()));
+        let speed = path_follow.speed * terrain.map_or(1.0, Terrain::speed_factor);
+        let step = speed * clock.delta_seconds();
+        let reached_end = path.advance(&mut path_follow.position, step, |node, edges| {
+            choose_branch(
+                path.choice,
+                edges,
+                |edge| branch_defense(&path, node, edge, &towers),
+                rng.gen(),
+            )
+        });
+        if reached_end {
             let mut base_health = base_query.single_mut();
-            base_health.damage(1);
+            base_health.damage(enemy.damage);
             commands.entity(entity).despawn_recursive();
-            audio.play(sounds.base_hit.clone());
+            base_hit_events.send(BaseHit);
+        }
+
+        transform.translation = path.sample(&path_follow.position).extend(ENEMY_Z);
+        match path.direction(&path_follow.position) {
+            Some(direction) => {
+                path_follow.velocity = direction * speed;
+                transform.rotation = Quat::from_rotation_z(direction.y.atan2(direction.x));
+            }
+            None => path_follow.velocity = Vec2::ZERO,
         }
-        transform.translation = path.lerp(path_follow.progress).extend(0.0);
     }
 }
 
 #[derive(Component)]
-struct EnemySpawner {
-    last_spawn_time: f64,
+pub struct EnemySpawner {
+    /// The path enemies from this spawner walk along.
+    pub path: Arc<Path>,
+    waves: Vec<Wave>,
+    /// Index of the wave currently being spawned.
+    wave: usize,
+    /// Number of enemies already spawned in the current wave.
+    spawned: u32,
+    next_spawn_time: f64,
 }
 
+impl EnemySpawner {
+    pub fn is_done(&self) -> bool {
+        self.wave >= self.waves.len()
+    }
+}
+
+/// Spawns a spawner at the start of `path`.
 pub struct SpawnEnemySpawner {
-    pub position: Coord,
+    pub path: Arc<Path>,
+    pub waves: Vec<Wave>,
 }
 
-fn enemy_spawner_spawn(
-    mut commands: Commands,
-    assets: Res<EnemySpawnerAssets>,
-    mut events: EventReader<SpawnEnemySpawner>,
-) {
+fn enemy_spawner_spawn(mut commands: Commands, mut events: EventReader<SpawnEnemySpawner>) {
     for event in events.iter() {
-        let position: Vec2 = event.position.into();
+        let position = event.path.sample(&event.path.start());
         commands
-            .spawn_bundle(ColorMesh2dBundle {
-                mesh: assets.mesh.clone(),
-                material: assets.material.clone(),
-                transform: Transform::from_translation(position.extend(1.0)),
-                ..Default::default()
-            })
+            .spawn_bundle(TransformBundle::from_transform(
+                Transform::from_translation(position.extend(1.0)),
+            ))
             .insert(EnemySpawner {
-                last_spawn_time: 0.0,
-            });
+                path: Arc::clone(&event.path),
+                waves: event.waves.clone(),
+                wave: 0,
+                spawned: 0,
+                next_spawn_time: 0.0,
+            })
+            .insert(MapEntity);
     }
 }
 
-pub struct PlayTime {
-    pub seconds: f64,
-}
-
-fn play_time_update(mut play_time: ResMut<PlayTime>, time: Res<Time>) {
-    play_time.seconds += time.delta_seconds_f64();
-}
-
 fn enemy_spawn(
     mut commands: Commands,
-    assets: Res<EnemyAssets>,
-    play_time: Res<PlayTime>,
+    enemy_kinds: Res<EnemyKinds>,
+    clock: Res<GameClock>,
+    mut current_wave: ResMut<CurrentWave>,
     mut query: Query<(&mut EnemySpawner, &Transform)>,
 ) {
     for (mut spawner, transform) in query.iter_mut() {
-        if play_time.seconds - spawner.last_spawn_time < 2.0 {
+        if spawner.is_done() || clock.elapsed() < spawner.next_spawn_time {
             continue;
         }
 
+        let wave = &spawner.waves[spawner.wave];
+        let (count, spacing, delay) = (wave.count.get(), wave.spacing, wave.delay);
+        current_wave.number = current_wave.number.max(spawner.wave + 1);
+
+        let kind_index = match enemy_kinds.index_of(&wave.enemy) {
+            Some(kind_index) => kind_index,
+            None => {
+                warn!("Skipping wave of unknown enemy kind '{}'", wave.enemy);
+                spawner.wave += 1;
+                spawner.spawned = 0;
+                spawner.next_spawn_time = clock.elapsed() + delay;
+                continue;
+            }
+        };
+        let kind = &enemy_kinds[kind_index];
+
         commands
-            .spawn_bundle(ColorMesh2dBundle {
-                mesh: assets.mesh.clone(),
-                material: assets.material.clone(),
-                transform: Transform::from_xyz(
-                    transform.translation.x,
-                    transform.translation.y,
-                    0.0,
-                ),
-                ..Default::default()
+            .spawn_bundle(TransformBundle::from_transform(Transform::from_xyz(
+                transform.translation.x,
+                transform.translation.y,
+                ENEMY_Z,
+            )))
+            .insert(Enemy {
+                kind: kind_index,
+                bounty: kind.bounty,
+                damage: kind.damage,
+                radius: kind.size,
             })
-            .insert(Enemy)
-            .insert(Health::new(6))
-            .insert(PathFollow { progress: 0.0 });
-
-        spawner.last_spawn_time = play_time.seconds;
+            .insert(Name::new(kind.name.clone()))
+            .insert(Health::new(kind.health))
+            .insert(PathFollow {
+                path: Arc::clone(&spawner.path),
+                position: spawner.path.start(),
+                speed: kind.speed * CELL_SIZE,
+                velocity: Vec2::ZERO,
+            })
+            .insert(MapEntity);
+
+        spawner.spawned += 1;
+        if spawner.spawned < count {
+            spawner.next_spawn_time = clock.elapsed() + spacing;
+        } else {
+            spawner.wave += 1;
+            spawner.spawned = 0;
+            spawner.next_spawn_time = clock.elapsed() + delay;
+        }
     }
 }
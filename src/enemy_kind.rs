@@ -0,0 +1,83 @@
+use bevy::{prelude::*, reflect::TypeUuid};
+use serde::Deserialize;
+
+use crate::ron_asset::RonAssetLoader;
+
+pub struct EnemyKindPlugin;
+
+impl Plugin for EnemyKindPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<EnemyKindList>()
+            .add_asset_loader(RonAssetLoader::<EnemyKindList>::new(&["enemies.ron"]))
+            .add_startup_system(enemy_kind_setup)
+            .add_system(enemy_kinds_update);
+    }
+}
+
+/// Stats and looks of an enemy. Waves refer to kinds by name.
+#[derive(Clone, Deserialize)]
+pub struct EnemyKind {
+    pub name: String,
+    pub health: i32,
+    /// Movement speed in cells per second.
+    pub speed: f32,
+    /// Coins paid out when the enemy is destroyed.
+    pub bounty: i32,
+    /// Damage dealt to the base when the enemy reaches it.
+    pub damage: i32,
+    /// Radius in world units.
+    pub size: f32,
+    pub sides: u32,
+    pub color: [f32; 3],
+}
+
+/// The contents of `assets/data/default.enemies.ron`.
+#[derive(Deserialize, TypeUuid)]
+#[serde(transparent)]
+#[uuid = "da0811cc-32dc-4a51-8985-5fb25bc7968d"]
+pub struct EnemyKindList {
+    kinds: Vec<EnemyKind>,
+}
+
+/// Every enemy kind, in the order of the enemy kind file. Only present once the
+/// file has been loaded.
+#[derive(Deref)]
+pub struct EnemyKinds(Vec<EnemyKind>);
+
+impl EnemyKinds {
+    pub fn index_of(&self, name: &str) -> Option<usize> {
+        self.iter().position(|kind| kind.name == name)
+    }
+}
+
+pub fn enemy_kinds_loaded(enemy_kinds: Option<Res<EnemyKinds>>) -> bool {
+    enemy_kinds.is_some()
+}
+
+struct EnemyKindListHandle(Handle<EnemyKindList>);
+
+fn enemy_kind_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(EnemyKindListHandle(
+        asset_server.load("data/default.enemies.ron"),
+    ));
+}
+
+/// Rebuilds [`EnemyKinds`] whenever the enemy kind file is (re)loaded.
+fn enemy_kinds_update(
+    mut commands: Commands,
+    mut events: EventReader<AssetEvent<EnemyKindList>>,
+    enemy_kind_list_handle: Res<EnemyKindListHandle>,
+    enemy_kind_lists: Res<Assets<EnemyKindList>>,
+) {
+    for event in events.iter() {
+        if let AssetEvent::Removed { .. } = event {
+            continue;
+        }
+        let kinds = match enemy_kind_lists.get(&enemy_kind_list_handle.0) {
+            Some(list) => list.kinds.clone(),
+            None => continue,
+        };
+
+        commands.insert_resource(EnemyKinds(kinds));
+    }
+}
@@ -0,0 +1,367 @@
+use bevy::{prelude::*, sprite::Mesh2dHandle};
+use iyes_loopless::prelude::*;
+
+use std::f32::consts::PI;
+
+use crate::{
+    base::Base,
+    enemy::{Enemy, EnemySpawner},
+    enemy_kind::EnemyKinds,
+    mesh::{MeshMaterial, RegPoly},
+    projectile::Projectile,
+    tile_map::{Terrain, TileMap},
+    tower::{BuildSpot, GridPosition, Tower, TowerUpgrades},
+    tower_kind::TowerKinds,
+};
+
+/// Gives gameplay entities their meshes. Gameplay plugins spawn entities with
+/// a transform only, so that they can run without rendering.
+pub struct EntityRenderPlugin;
+
+impl Plugin for EntityRenderPlugin {
+    fn build(&self, app: &mut App) {
+        // Entities spawned during `Update` get their meshes and children before
+        // transforms are propagated, so nothing shows up at the origin for a
+        // frame.
+        app.add_stage_after(
+            CoreStage::Update,
+            EntityRenderStage,
+            SystemStage::parallel(),
+        )
+        .add_startup_system(entity_render_setup)
+        .add_system(enemy_kind_assets_update.run_if_resource_exists::<EnemyKinds>())
+        .add_system(tower_kind_assets_update.run_if_resource_exists::<TowerKinds>())
+        .add_system_set_to_stage(
+            EntityRenderStage,
+            SystemSet::new()
+                .with_system(enemy_spawner_render)
+                .with_system(enemy_render.run_if_resource_exists::<EnemyKindAssetList>())
+                .with_system(base_render)
+                .with_system(projectile_render)
+                .with_system(build_spot_render)
+                .with_system(tower_render.run_if_resource_exists::<TowerKindAssetList>())
+                .with_system(tower_upgrade_render.run_if_resource_exists::<TowerKindAssetList>()),
+        );
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, StageLabel)]
+struct EntityRenderStage;
+
+struct EntityRenderAssets {
+    enemy_spawner: MeshMaterial,
+    base: MeshMaterial,
+    projectile: MeshMaterial,
+    build_spot: MeshMaterial,
+    high_ground: Handle<ColorMaterial>,
+    upgrade_pip: Mesh2dHandle,
+}
+
+fn entity_render_setup(
+    mut commands: Commands,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    commands.insert_resource(EntityRenderAssets {
+        enemy_spawner: MeshMaterial {
+            mesh: Mesh2dHandle(meshes.add(RegPoly::fill(6, 14.0).into())),
+            material: materials.add(Color::rgb(0.4, 0.2, 0.6).into()),
+        },
+        base: MeshMaterial {
+            mesh: Mesh2dHandle(meshes.add(RegPoly::fill(6, 12.0).into())),
+            material: materials.add(Color::rgb(6.0, 0.6, 0.2).into()),
+        },
+        projectile: MeshMaterial {
+            mesh: Mesh2dHandle(meshes.add(RegPoly::fill(8, 2.0).into())),
+            material: materials.add(Color::rgb(0.1, 0.1, 0.1).into()),
+        },
+        build_spot: MeshMaterial {
+            mesh: Mesh2dHandle(meshes.add(shape::Quad::new(Vec2::new(30.0, 30.0)).into())),
+            material: materials.add(Color::rgb(0.3, 0.3, 0.3).into()),
+        },
+        high_ground: materials.add(Color::rgb(0.45, 0.42, 0.35).into()),
+        upgrade_pip: Mesh2dHandle(meshes.add(RegPoly::fill(8, 3.0).into())),
+    });
+}
+
+/// What a [`ColorMesh2dBundle`] adds on top of a transform.
+fn visuals(
+    mesh: &Mesh2dHandle,
+    material: &Handle<ColorMaterial>,
+) -> (
+    Mesh2dHandle,
+    Handle<ColorMaterial>,
+    Visibility,
+    ComputedVisibility,
+) {
+    (
+        mesh.clone(),
+        material.clone(),
+        Visibility::default(),
+        ComputedVisibility::default(),
+    )
+}
+
+/// Mesh and material for each entry in [`EnemyKinds`]. Rebuilt the frame after
+/// the kinds change, so entities of new kinds may only be rendered a frame
+/// late.
+#[derive(Deref)]
+struct EnemyKindAssetList(Vec<MeshMaterial>);
+
+fn enemy_kind_assets_update(
+    mut commands: Commands,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    enemy_kinds: Res<EnemyKinds>,
+) {
+    if !enemy_kinds.is_changed() {
+        return;
+    }
+    let assets = enemy_kinds
+        .iter()
+        .map(|kind| MeshMaterial {
+            mesh: Mesh2dHandle(meshes.add(RegPoly::fill(kind.sides, kind.size).into())),
+            material: materials.add(Color::rgb(kind.color[0], kind.color[1], kind.color[2]).into()),
+        })
+        .collect();
+    commands.insert_resource(EnemyKindAssetList(assets));
+}
+
+struct TowerKindAssets {
+    base: MeshMaterial,
+    barrel: MeshMaterial,
+    barrel_cap: MeshMaterial,
+    /// One material per upgrade branch.
+    branches: Vec<Handle<ColorMaterial>>,
+}
+
+/// Meshes and materials for each entry in [`TowerKinds`].
+#[derive(Deref)]
+struct TowerKindAssetList(Vec<TowerKindAssets>);
+
+fn tower_kind_assets_update(
+    mut commands: Commands,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    tower_kinds: Res<TowerKinds>,
+) {
+    if !tower_kinds.is_changed() {
+        return;
+    }
+    let assets = tower_kinds
+        .iter()
+        .map(|kind| {
+            let visuals = &kind.visuals;
+            let barrel_color = Color::rgb(
+                visuals.barrel_color[0],
+                visuals.barrel_color[1],
+                visuals.barrel_color[2],
+            );
+            TowerKindAssets {
+                base: MeshMaterial {
+                    mesh: Mesh2dHandle(
+                        meshes.add(RegPoly::fill(visuals.sides, visuals.radius).into()),
+                    ),
+                    material: materials.add(
+                        Color::rgb(visuals.color[0], visuals.color[1], visuals.color[2]).into(),
+                    ),
+                },
+                barrel: MeshMaterial {
+                    mesh: Mesh2dHandle(
+                        meshes.add(
+                            shape::Quad::new(Vec2::new(
+                                visuals.barrel_length,
+                                visuals.barrel_width,
+                            ))
+                            .into(),
+                        ),
+                    ),
+                    material: materials.add(barrel_color.into()),
+                },
+                barrel_cap: MeshMaterial {
+                    mesh: Mesh2dHandle(
+                        meshes
+                            .add(shape::Quad::new(Vec2::splat(visuals.barrel_width * 2.0)).into()),
+                    ),
+                    material: materials.add(barrel_color.into()),
+                },
+                branches: kind
+                    .upgrades
+                    .iter()
+                    .map(|branch| {
+                        materials.add(
+                            Color::rgb(branch.color[0], branch.color[1], branch.color[2]).into(),
+                        )
+                    })
+                    .collect(),
+            }
+        })
+        .collect();
+    commands.insert_resource(TowerKindAssetList(assets));
+}
+
+fn enemy_spawner_render(
+    mut commands: Commands,
+    assets: Res<EntityRenderAssets>,
+    query: Query<Entity, Added<EnemySpawner>>,
+) {
+    for entity in query.iter() {
+        let assets = &assets.enemy_spawner;
+        commands
+            .entity(entity)
+            .insert_bundle(visuals(&assets.mesh, &assets.material));
+    }
+}
+
+fn enemy_render(
+    mut commands: Commands,
+    assets: Res<EnemyKindAssetList>,
+    query: Query<(Entity, &Enemy), Without<Mesh2dHandle>>,
+) {
+    for (entity, enemy) in query.iter() {
+        let assets = match assets.get(enemy.kind) {
+            Some(assets) => assets,
+            None => continue,
+        };
+        commands
+            .entity(entity)
+            .insert_bundle(visuals(&assets.mesh, &assets.material));
+    }
+}
+
+fn base_render(
+    mut commands: Commands,
+    assets: Res<EntityRenderAssets>,
+    query: Query<Entity, Added<Base>>,
+) {
+    for entity in query.iter() {
+        let assets = &assets.base;
+        commands
+            .entity(entity)
+            .insert_bundle(visuals(&assets.mesh, &assets.material));
+    }
+}
+
+fn projectile_render(
+    mut commands: Commands,
+    assets: Res<EntityRenderAssets>,
+    query: Query<Entity, Added<Projectile>>,
+) {
+    for entity in query.iter() {
+        let assets = &assets.projectile;
+        commands
+            .entity(entity)
+            .insert_bundle(visuals(&assets.mesh, &assets.material));
+    }
+}
+
+fn build_spot_render(
+    mut commands: Commands,
+    assets: Res<EntityRenderAssets>,
+    tile_map: Option<Res<TileMap>>,
+    query: Query<(Entity, &GridPosition), Added<BuildSpot>>,
+) {
+    for (entity, position) in query.iter() {
+        let terrain = tile_map
+            .as_deref()
+            .and_then(|tile_map| tile_map.get(**position));
+        let material = match terrain {
+            Some(Terrain::HighGround) => &assets.high_ground,
+            _ => &assets.build_spot.material,
+        };
+        commands
+            .entity(entity)
+            .insert_bundle(visuals(&assets.build_spot.mesh, material));
+    }
+}
+
+fn tower_render(
+    mut commands: Commands,
+    tower_kinds: Res<TowerKinds>,
+    assets: Res<TowerKindAssetList>,
+    query: Query<(Entity, &Tower), Without<Mesh2dHandle>>,
+) {
+    for (entity, tower) in query.iter() {
+        let (kind, assets) = match (tower_kinds.get(tower.kind), assets.get(tower.kind)) {
+            (Some(kind), Some(assets)) => (kind, assets),
+            _ => continue,
+        };
+        commands
+            .entity(entity)
+            .insert_bundle(visuals(&assets.base.mesh, &assets.base.material))
+            .with_children(|parent| {
+                parent.spawn_bundle(ColorMesh2dBundle {
+                    mesh: assets.barrel.mesh.clone(),
+                    material: assets.barrel.material.clone(),
+                    transform: Transform::from_xyz(kind.visuals.barrel_length * 0.5, 0.0, 2.0),
+                    ..Default::default()
+                });
+                parent.spawn_bundle(ColorMesh2dBundle {
+                    mesh: assets.barrel_cap.mesh.clone(),
+                    material: assets.barrel_cap.material.clone(),
+                    transform: Transform::from_xyz(0.0, 0.0, 2.0),
+                    ..Default::default()
+                });
+            });
+    }
+}
+
+/// Angle between two upgrade pips on the rim of a tower.
+const PIP_SPACING: f32 = 0.6;
+
+#[derive(Component)]
+struct UpgradePip;
+
+/// Recolors upgraded towers and gives them one pip per level, opposite the
+/// barrel. Towers upgraded before they were rendered catch up once they are.
+fn tower_upgrade_render(
+    mut commands: Commands,
+    tower_kinds: Res<TowerKinds>,
+    kind_assets: Res<TowerKindAssetList>,
+    assets: Res<EntityRenderAssets>,
+    mut tower_query: Query<
+        (
+            Entity,
+            &Tower,
+            &TowerUpgrades,
+            &mut Handle<ColorMaterial>,
+            &Children,
+        ),
+        Or<(Changed<TowerUpgrades>, Added<Mesh2dHandle>)>,
+    >,
+    pip_query: Query<(), With<UpgradePip>>,
+) {
+    for (entity, tower, upgrades, mut material, children) in tower_query.iter_mut() {
+        let branch_material = upgrades.branch.and_then(|branch| {
+            kind_assets
+                .get(tower.kind)
+                .and_then(|assets| assets.branches.get(branch))
+        });
+        let (branch_material, kind) = match (branch_material, tower_kinds.get(tower.kind)) {
+            (Some(branch_material), Some(kind)) => (branch_material, kind),
+            _ => continue,
+        };
+        *material = branch_material.clone();
+
+        for &child in children.iter() {
+            if pip_query.get(child).is_ok() {
+                commands.entity(child).despawn_recursive();
+            }
+        }
+        let radius = kind.visuals.radius;
+        commands.entity(entity).with_children(|parent| {
+            for level in 0..upgrades.level {
+                let pip_angle = PI + level as f32 * PIP_SPACING;
+                let pip_position = Vec2::new(pip_angle.cos(), pip_angle.sin()) * radius;
+                parent
+                    .spawn_bundle(ColorMesh2dBundle {
+                        mesh: assets.upgrade_pip.clone(),
+                        material: branch_material.clone(),
+                        transform: Transform::from_translation(pip_position.extend(3.0)),
+                        ..Default::default()
+                    })
+                    .insert(UpgradePip);
+            }
+        });
+    }
+}
@@ -0,0 +1,275 @@
+use bevy::prelude::*;
+use iyes_loopless::prelude::*;
+
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet},
+    sync::Arc,
+};
+
+use crate::{
+    coord::Coord,
+    enemy::{EnemySpawner, PathFollow},
+    path::{Path, PathPosition},
+    simulation::{SimulationAppExt, SimulationPhase},
+    tile_map::TileMap,
+    tower::{GridPosition, Tower, TowerChanged},
+};
+
+pub struct FieldPlugin;
+
+impl Plugin for FieldPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_simulation_system(
+            SimulationPhase::Spawn,
+            field_repath.run_if_resource_exists::<OpenField>(),
+        );
+    }
+}
+
+/// Present while playing an open-field map, where towers can be built on any
+/// free buildable cell and enemies find their own way to the base around them.
+pub struct OpenField {
+    pub base: Coord,
+    /// Where the spawners are.
+    pub entrances: Vec<Coord>,
+}
+
+impl OpenField {
+    /// Fastest route from `start` to the base over walkable terrain, avoiding
+    /// `blocked` cells.
+    pub fn route(
+        &self,
+        tile_map: &TileMap,
+        start: Coord,
+        blocked: &HashSet<Coord>,
+    ) -> Option<Vec<Coord>> {
+        find_route(start, self.base, |coord| {
+            let terrain = tile_map.get(coord)?;
+            if !terrain.is_walkable() || blocked.contains(&coord) {
+                return None;
+            }
+            // Slow terrain costs proportionally more to cross.
+            Some((1.0 / terrain.speed_factor()).round() as i32)
+        })
+    }
+
+    /// A new path to the base for an enemy at `position` on `path`, and the
+    /// enemy's position on it. The enemy carries on to the cell it is walking
+    /// to, or turns back if that cell has been blocked or the shortest way
+    /// leads back anyway.
+    pub fn reroute(
+        &self,
+        tile_map: &TileMap,
+        path: &Path,
+        position: &PathPosition,
+        blocked: &HashSet<Coord>,
+    ) -> Option<(Path, PathPosition)> {
+        let (from, to, distance) = match path.edge_at(position) {
+            Some(edge) => edge,
+            None => {
+                let path = Path::new(&self.route(tile_map, path.node_at(position)?, blocked)?);
+                let position = path.start();
+                return Some((path, position));
+            }
+        };
+
+        let ahead = if blocked.contains(&to) {
+            None
+        } else {
+            self.route(tile_map, to, blocked)
+                .filter(|route| route.get(1) != Some(&from))
+        };
+        let (nodes, distance) = match ahead {
+            Some(route) => ([vec![from], route].concat(), distance),
+            None => {
+                let length = Vec2::from(from).distance(to.into());
+                (
+                    [vec![to], self.route(tile_map, from, blocked)?].concat(),
+                    length - distance,
+                )
+            }
+        };
+
+        let path = Path::new(&nodes);
+        let mut position = path.start();
+        path.advance(&mut position, distance, |_, _| 0);
+        Some((path, position))
+    }
+}
+
+/// Cheapest route from `start` to `goal` over orthogonally adjacent cells,
+/// using A*. `step_cost` is the cost of entering a cell, at least 1, or `None`
+/// if it cannot be entered. `start` itself is always allowed.
+pub fn find_route(
+    start: Coord,
+    goal: Coord,
+    step_cost: impl Fn(Coord) -> Option<i32>,
+) -> Option<Vec<Coord>> {
+    let heuristic = |coord: Coord| (coord.x - goal.x).abs() + (coord.y - goal.y).abs();
+
+    let mut open = BinaryHeap::new();
+    let mut came_from = HashMap::new();
+    let mut costs = HashMap::from([(start, 0)]);
+    open.push(Reverse((heuristic(start), 0, start.x, start.y)));
+
+    while let Some(Reverse((_, cost, x, y))) = open.pop() {
+        let current = Coord::new(x, y);
+        if current == goal {
+            let mut route = vec![current];
+            while let Some(&previous) = came_from.get(route.last().unwrap()) {
+                route.push(previous);
+            }
+            route.reverse();
+            return Some(route);
+        }
+        if cost > costs[&current] {
+            // Already reached more cheaply.
+            continue;
+        }
+
+        for (dx, dy) in [(1, 0), (0, 1), (-1, 0), (0, -1)] {
+            let next = Coord::new(x + dx, y + dy);
+            let next_cost = match step_cost(next) {
+                Some(step_cost) => cost + step_cost,
+                None => continue,
+            };
+            if matches!(costs.get(&next), Some(&old) if old <= next_cost) {
+                continue;
+            }
+            costs.insert(next, next_cost);
+            came_from.insert(next, current);
+            open.push(Reverse((
+                next_cost + heuristic(next),
+                next_cost,
+                next.x,
+                next.y,
+            )));
+        }
+    }
+    None
+}
+
+/// Cells taken by towers.
+pub fn blocked_cells<'a>(towers: impl IntoIterator<Item = &'a GridPosition>) -> HashSet<Coord> {
+    towers.into_iter().map(|position| **position).collect()
+}
+
+/// Sends spawners and walking enemies around towers whenever one is built or
+/// sold. Runs before enemies are spawned, so none leave on an outdated path.
+fn field_repath(
+    field: Res<OpenField>,
+    tile_map: Res<TileMap>,
+    mut changed_events: EventReader<TowerChanged>,
+    tower_query: Query<&GridPosition, With<Tower>>,
+    mut spawner_query: Query<&mut EnemySpawner>,
+    mut enemy_query: Query<&mut PathFollow>,
+) {
+    let blocking_changed = changed_events
+        .iter()
+        .filter(|event| !matches!(event, TowerChanged::Upgraded))
+        .count()
+        > 0;
+    if !blocking_changed {
+        return;
+    }
+    let blocked = blocked_cells(tower_query.iter());
+
+    for mut spawner in spawner_query.iter_mut() {
+        let entrance = spawner.path.node_at(&spawner.path.start());
+        if let Some(route) =
+            entrance.and_then(|entrance| field.route(&tile_map, entrance, &blocked))
+        {
+            spawner.path = Arc::new(Path::new(&route));
+        }
+    }
+
+    for mut path_follow in enemy_query.iter_mut() {
+        if let Some((path, position)) = field.reroute(
+            &tile_map,
+            &path_follow.path,
+            &path_follow.position,
+            &blocked,
+        ) {
+            path_follow.path = Arc::new(path);
+            path_follow.position = position;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{
+        coord::CELL_SIZE,
+        tile_map::{Bounds, Terrain},
+    };
+
+    fn field() -> (OpenField, TileMap) {
+        let field = OpenField {
+            base: Coord::new(4, 2),
+            entrances: vec![Coord::new(0, 2)],
+        };
+        let bounds = Bounds {
+            min: Coord::new(0, 0),
+            max: Coord::new(4, 4),
+        };
+        (field, TileMap::new(bounds, Terrain::Buildable))
+    }
+
+    fn cells(coords: &[(i32, i32)]) -> HashSet<Coord> {
+        coords.iter().map(|&coord| coord.into()).collect()
+    }
+
+    #[test]
+    fn routes_around_towers() {
+        let (field, tile_map) = field();
+        let straight = field
+            .route(&tile_map, Coord::new(0, 2), &HashSet::new())
+            .unwrap();
+        assert_eq!(straight.len(), 5);
+
+        let wall = cells(&[(2, 0), (2, 1), (2, 2), (2, 3)]);
+        let route = field.route(&tile_map, Coord::new(0, 2), &wall).unwrap();
+        assert_eq!(route.first(), Some(&Coord::new(0, 2)));
+        assert_eq!(route.last(), Some(&Coord::new(4, 2)));
+        assert!(route.contains(&Coord::new(2, 4)));
+        // Around the wall and back: 4 moves across, 2 up and 2 down.
+        assert_eq!(route.len(), 9);
+        assert!(route
+            .windows(2)
+            .all(|step| { (step[0].x - step[1].x).abs() + (step[0].y - step[1].y).abs() == 1 }));
+    }
+
+    #[test]
+    fn no_route_through_a_full_wall() {
+        let (field, mut tile_map) = field();
+        let wall = cells(&[(2, 0), (2, 1), (2, 2), (2, 3)]);
+        assert!(field.route(&tile_map, Coord::new(0, 2), &wall).is_some());
+
+        // Blocked terrain closes the last gap just like a tower.
+        tile_map.set(Coord::new(2, 4), Terrain::Blocked);
+        assert_eq!(field.route(&tile_map, Coord::new(0, 2), &wall), None);
+    }
+
+    #[test]
+    fn rerouted_enemy_turns_back_from_blocked_cell() {
+        let (field, tile_map) = field();
+        let route = field.route(&tile_map, Coord::new(0, 2), &HashSet::new());
+        let path = Path::new(&route.unwrap());
+        let mut position = path.start();
+        path.advance(&mut position, 40.0, |_, _| 0);
+        let before = path.sample(&position);
+
+        // A tower goes up on (2, 2), which the enemy is walking towards.
+        let blocked = cells(&[(2, 2)]);
+        let (path, position) = field
+            .reroute(&tile_map, &path, &position, &blocked)
+            .unwrap();
+        assert_eq!(path.sample(&position), before);
+        assert_eq!(path.edge_at(&position).unwrap().1, Coord::new(1, 2));
+        // Back a quarter of a cell, then around the tower.
+        assert_eq!(path.remaining(&position), 8.0 + 5.0 * CELL_SIZE);
+    }
+}
@@ -2,9 +2,13 @@ use bevy::prelude::*;
 use iyes_loopless::prelude::*;
 
 use crate::{
-    audio::AudioPlugin, base::BasePlugin, currency::CurrencyPlugin, enemy::EnemyPlugin,
-    game_state::GameState, map::MapPlugin, projectile::ProjectilePlugin, tower::TowerPlugin,
-    ui::UiPlugin,
+    audio::AudioPlugin, base::BasePlugin, camera::CameraPlugin, clock::ClockPlugin,
+    currency::CurrencyPlugin, editor::EditorPlugin, enemy::EnemyPlugin,
+    enemy_kind::EnemyKindPlugin, entity_render::EntityRenderPlugin, field::FieldPlugin,
+    game_state::GameState, map::MapPlugin, map_render::MapRenderPlugin, menu::MenuPlugin,
+    projectile::ProjectilePlugin, records::RecordsPlugin, simulation::SimulationPlugin,
+    stats::StatsPlugin, tile_map::TileMapPlugin, tower::TowerPlugin, tower_input::TowerInputPlugin,
+    tower_kind::TowerKindPlugin, ui::UiPlugin, wave::WavePlugin,
 };
 
 pub struct GamePlugin;
@@ -12,20 +16,46 @@ pub struct GamePlugin;
 impl Plugin for GamePlugin {
     fn build(&self, app: &mut App) {
         app.insert_resource(ClearColor(Color::rgb(0.2, 0.2, 0.2)))
-            .add_loopless_state(GameState::LoadingMap)
+            .add_plugin(GameplayPlugin)
+            .add_plugin(CameraPlugin)
+            .add_plugin(ClockPlugin)
+            .add_plugin(TowerInputPlugin)
+            .add_plugin(TileMapPlugin)
+            .add_plugin(MapRenderPlugin)
+            .add_plugin(EntityRenderPlugin)
+            .add_plugin(UiPlugin)
+            .add_plugin(AudioPlugin)
+            .add_plugin(EditorPlugin)
+            .add_plugin(MenuPlugin)
+            .add_plugin(RecordsPlugin)
+            .add_startup_system(game_setup);
+    }
+}
+
+/// The game itself, without any window, input, audio or rendering. Only needs
+/// the asset server on top of `MinimalPlugins`.
+pub struct GameplayPlugin;
+
+impl Plugin for GameplayPlugin {
+    fn build(&self, app: &mut App) {
+        // Simulation systems of a phase run in the order their plugins are
+        // added, so towers are routed around before enemies are spawned.
+        app.add_loopless_state(GameState::MainMenu)
+            .add_plugin(SimulationPlugin)
+            .add_plugin(FieldPlugin)
             .add_plugin(EnemyPlugin)
+            .add_plugin(EnemyKindPlugin)
             .add_plugin(ProjectilePlugin)
             .add_plugin(TowerPlugin)
+            .add_plugin(TowerKindPlugin)
             .add_plugin(MapPlugin)
             .add_plugin(BasePlugin)
             .add_plugin(CurrencyPlugin)
-            .add_plugin(UiPlugin)
-            .add_plugin(AudioPlugin)
-            .add_startup_system(game_setup);
+            .add_plugin(WavePlugin)
+            .add_plugin(StatsPlugin);
     }
 }
 
 fn game_setup(mut commands: Commands) {
-    commands.spawn_bundle(OrthographicCameraBundle::new_2d());
     commands.spawn_bundle(UiCameraBundle::default());
 }
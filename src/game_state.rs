@@ -1,7 +1,10 @@
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum GameState {
+    MainMenu,
     LoadingMap,
     Playing,
     Paused,
     GameOver,
+    Victory,
+    Editor,
 }
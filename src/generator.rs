@@ -0,0 +1,318 @@
+use bevy::utils::default;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use std::{collections::HashSet, num::NonZeroU32};
+
+use crate::{
+    coord::Coord,
+    map::{Lane, Map},
+    path::BranchChoice,
+    tile_map::{path_cells, Bounds},
+    wave::Wave,
+};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Difficulty {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    pub const ALL: [Difficulty; 3] = [Difficulty::Easy, Difficulty::Normal, Difficulty::Hard];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Difficulty::Easy => "Easy",
+            Difficulty::Normal => "Normal",
+            Difficulty::Hard => "Hard",
+        }
+    }
+
+    /// Chance of heading straight for the base at each step of the path.
+    /// Harder maps have shorter paths.
+    fn directness(self) -> f64 {
+        match self {
+            Difficulty::Easy => 0.2,
+            Difficulty::Normal => 0.45,
+            Difficulty::Hard => 0.75,
+        }
+    }
+
+    /// Share of the cells next to the path that become build spots.
+    fn build_spot_share(self) -> f64 {
+        match self {
+            Difficulty::Easy => 0.7,
+            Difficulty::Normal => 0.5,
+            Difficulty::Hard => 0.35,
+        }
+    }
+
+    fn waves(self) -> usize {
+        match self {
+            Difficulty::Easy => 6,
+            Difficulty::Normal => 8,
+            Difficulty::Hard => 10,
+        }
+    }
+
+    fn enemy_count_factor(self) -> f32 {
+        match self {
+            Difficulty::Easy => 0.8,
+            Difficulty::Normal => 1.0,
+            Difficulty::Hard => 1.3,
+        }
+    }
+
+    fn starting_coins(self) -> i32 {
+        match self {
+            Difficulty::Easy => 14,
+            Difficulty::Normal => 10,
+            Difficulty::Hard => 8,
+        }
+    }
+}
+
+impl std::str::FromStr for Difficulty {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Difficulty::ALL
+            .into_iter()
+            .find(|difficulty| difficulty.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("Unknown difficulty '{}'", s))
+    }
+}
+
+/// Everything a generated map depends on. The same settings always generate
+/// the same map.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GeneratorSettings {
+    pub seed: u64,
+    /// Size of the map in cells.
+    pub width: i32,
+    pub height: i32,
+    pub difficulty: Difficulty,
+}
+
+impl Default for GeneratorSettings {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            width: 25,
+            height: 13,
+            difficulty: default(),
+        }
+    }
+}
+
+/// Enemy kinds used in generated waves, along with the first wave they can
+/// appear in, how many of them make up a wave and the seconds between them.
+const WAVE_ENEMIES: [(&str, usize, f32, f64); 4] = [
+    ("grunt", 0, 5.0, 1.5),
+    ("runner", 1, 6.0, 1.0),
+    ("swarmling", 3, 15.0, 0.4),
+    ("tank", 4, 2.0, 3.0),
+];
+
+/// Generates a map with a single winding lane from the left edge to the right
+/// edge, and build spots along it.
+pub fn generate_map(settings: &GeneratorSettings) -> Map {
+    let mut rng = StdRng::seed_from_u64(settings.seed);
+    let difficulty = settings.difficulty;
+
+    // The path walks a grid of every other cell, so that there is always a
+    // free cell between two stretches of path running side by side.
+    let columns = ((settings.width + 1) / 2).max(2);
+    let rows = ((settings.height + 1) / 2).max(1);
+    let walk = random_walk(&mut rng, columns, rows, difficulty.directness());
+    let cells: Vec<Coord> = walk
+        .iter()
+        .map(|&(column, row)| Coord::new(2 * column - (columns - 1), 2 * row - (rows - 1)))
+        .collect();
+    let path = corners(&cells);
+    let base = *path.last().unwrap();
+
+    let bounds = Bounds {
+        min: Coord::new(-(columns - 1), -(rows - 1)),
+        max: Coord::new(columns - 1, rows - 1),
+    };
+    let on_path: HashSet<Coord> = path_cells(&path).collect();
+    let mut seen = HashSet::new();
+    let mut build_spots = Vec::new();
+    for cell in path_cells(&path) {
+        for (dx, dy) in [
+            (-1, -1),
+            (0, -1),
+            (1, -1),
+            (-1, 0),
+            (1, 0),
+            (-1, 1),
+            (0, 1),
+            (1, 1),
+        ] {
+            let neighbour = Coord::new(cell.x + dx, cell.y + dy);
+            if bounds.contains(neighbour) && !on_path.contains(&neighbour) && seen.insert(neighbour)
+            {
+                // Decide for every candidate, kept or not, so that the choice
+                // stays the same whatever comes next.
+                if rng.gen_bool(difficulty.build_spot_share()) {
+                    build_spots.push(neighbour);
+                }
+            }
+        }
+    }
+
+    let wave_count = difficulty.waves();
+    let waves = (0..wave_count)
+        .map(|i| {
+            let unlocked: Vec<_> = WAVE_ENEMIES
+                .iter()
+                .filter(|&&(_, first_wave, _, _)| first_wave <= i)
+                .collect();
+            let &&(enemy, _, count, spacing) = unlocked.choose(&mut rng).unwrap();
+            let count = count * (1.0 + i as f32 / 3.0) * difficulty.enemy_count_factor();
+            Wave {
+                enemy: enemy.to_string(),
+                count: NonZeroU32::new(count.round().max(1.0) as u32).unwrap(),
+                spacing,
+                delay: if i + 1 < wave_count { 8.0 } else { 0.0 },
+            }
+        })
+        .collect();
+
+    Map {
+        name: format!("Random #{}", settings.seed),
+        difficulty,
+        starting_coins: difficulty.starting_coins(),
+        sell_refund: default(),
+        base,
+        lanes: vec![Lane {
+            path,
+            branches: Vec::new(),
+            choice: BranchChoice::default(),
+            waves,
+        }],
+        build_spots,
+        open_field: None,
+        terrain: Vec::new(),
+    }
+}
+
+/// A self-avoiding walk from a random cell of the first column to any cell of
+/// the last one, found by a randomized depth-first search.
+fn random_walk(rng: &mut StdRng, columns: i32, rows: i32, directness: f64) -> Vec<(i32, i32)> {
+    let index = |(column, row): (i32, i32)| (row * columns + column) as usize;
+    let mut visited = vec![false; (columns * rows) as usize];
+    let start = (0, rng.gen_range(0..rows));
+    visited[index(start)] = true;
+
+    let mut walk = vec![start];
+    while let Some(&(column, row)) = walk.last() {
+        if column == columns - 1 {
+            break;
+        }
+        let options: Vec<(i32, i32)> = [(1, 0), (0, -1), (0, 1), (-1, 0)]
+            .into_iter()
+            .map(|(dx, dy)| (column + dx, row + dy))
+            .filter(|&(x, y)| (0..columns).contains(&x) && (0..rows).contains(&y))
+            .filter(|&cell| !visited[index(cell)])
+            .collect();
+        let next = match options.first() {
+            // Every cell stays visited when backing out of a dead end, so the
+            // search visits each cell at most once.
+            None => {
+                walk.pop();
+                continue;
+            }
+            Some(&ahead) if ahead == (column + 1, row) && rng.gen_bool(directness) => ahead,
+            Some(_) => *options.choose(rng).unwrap(),
+        };
+        visited[index(next)] = true;
+        walk.push(next);
+    }
+    walk
+}
+
+/// The cells of an orthogonal walk where it starts, turns or ends.
+fn corners(cells: &[Coord]) -> Vec<Coord> {
+    let mut corners: Vec<Coord> = cells.first().copied().into_iter().collect();
+    for window in cells.windows(3) {
+        let (before, cell, after) = (window[0], window[1], window[2]);
+        if (cell.x - before.x, cell.y - before.y) != (after.x - cell.x, after.y - cell.y) {
+            corners.push(cell);
+        }
+    }
+    if cells.len() > 1 {
+        corners.push(*cells.last().unwrap());
+    }
+    corners
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::validation::validate_map;
+
+    fn settings() -> impl Iterator<Item = GeneratorSettings> {
+        Difficulty::ALL.into_iter().flat_map(|difficulty| {
+            (0..40).map(move |seed| GeneratorSettings {
+                seed,
+                difficulty,
+                ..default()
+            })
+        })
+    }
+
+    #[test]
+    fn generated_maps_are_valid() {
+        for settings in settings() {
+            let map = generate_map(&settings);
+            assert_eq!(validate_map(&map), [], "{:?}", settings);
+        }
+    }
+
+    #[test]
+    fn paths_are_orthogonal_and_do_not_cross_themselves() {
+        for settings in settings() {
+            let map = generate_map(&settings);
+            let path = &map.lanes[0].path;
+            assert!(path.len() >= 2);
+            for segment in path.windows(2) {
+                assert!(segment[0].x == segment[1].x || segment[0].y == segment[1].y);
+            }
+
+            let mut cells: Vec<Coord> = path_cells(path).collect();
+            // Consecutive segments share their corner.
+            cells.dedup();
+            let unique: HashSet<Coord> = cells.iter().copied().collect();
+            assert_eq!(unique.len(), cells.len(), "{:?}", settings);
+        }
+    }
+
+    #[test]
+    fn build_spots_are_next_to_the_path() {
+        for settings in settings() {
+            let map = generate_map(&settings);
+            let cells: Vec<Coord> = path_cells(&map.lanes[0].path).collect();
+            assert!(!map.build_spots.is_empty());
+            for spot in &map.build_spots {
+                assert!(cells
+                    .iter()
+                    .any(|cell| (cell.x - spot.x).abs() <= 1 && (cell.y - spot.y).abs() <= 1));
+            }
+        }
+    }
+
+    #[test]
+    fn same_seed_generates_the_same_map() {
+        let to_ron = |seed| {
+            let settings = GeneratorSettings { seed, ..default() };
+            ron::to_string(&generate_map(&settings)).unwrap()
+        };
+        assert_eq!(to_ron(7), to_ron(7));
+        assert_ne!(to_ron(7), to_ron(8));
+    }
+}
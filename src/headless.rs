@@ -0,0 +1,283 @@
+use anyhow::{anyhow, bail};
+use bevy::{asset::AssetPlugin, prelude::*};
+use iyes_loopless::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use std::{
+    fs,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    base::Base,
+    clock::GameClock,
+    coord::Coord,
+    currency::Currency,
+    game::GameplayPlugin,
+    game_state::GameState,
+    health::Health,
+    map::{Map, MapSource},
+    ron_asset::{asset_path, parse_ron},
+    simulation::{SimulationAppExt, SimulationPhase, TicksPerUpdate},
+    stats::GameStats,
+    tower::{GridPosition, SpawnTower, Tower, TowerUpgrades, UpgradeTower},
+    tower_kind::TowerKinds,
+    wave::{CurrentWave, Wave},
+};
+
+/// A map, its waves and a build plan to play through without a window.
+#[derive(Clone, Deserialize)]
+pub struct Scenario {
+    /// Map file, relative to the assets directory.
+    pub map: String,
+    /// Waves sent down every lane instead of the map's own.
+    #[serde(default)]
+    pub waves: Option<Vec<Wave>>,
+    /// Carried out in order. Each step waits for its time, then for enough
+    /// coins.
+    #[serde(default)]
+    pub builds: Vec<BuildStep>,
+    /// Seconds of game time after which the run is given up.
+    #[serde(default = "default_time_limit")]
+    pub time_limit: f64,
+}
+
+fn default_time_limit() -> f64 {
+    3600.0
+}
+
+#[derive(Clone, Deserialize)]
+pub enum BuildStep {
+    /// Builds a tower of the named kind.
+    Build { time: f64, tower: String, at: Coord },
+    /// Buys the next level along `branch` for the tower on `at`.
+    Upgrade { time: f64, at: Coord, branch: usize },
+}
+
+impl BuildStep {
+    fn time(&self) -> f64 {
+        match self {
+            BuildStep::Build { time, .. } | BuildStep::Upgrade { time, .. } => *time,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Victory,
+    Defeat,
+    /// Still going at the scenario's time limit.
+    Timeout,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct SimulationResult {
+    pub map: String,
+    pub outcome: Outcome,
+    /// Seconds of game time played.
+    pub duration: f64,
+    pub base_health: i32,
+    /// The last wave reached.
+    pub wave: usize,
+    pub kills: u32,
+    pub towers: Vec<TowerResult>,
+    /// Coins at every second of game time.
+    pub coins: Vec<CoinSample>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct TowerResult {
+    pub kind: String,
+    pub position: Coord,
+    /// Coins spent on the tower, including upgrades.
+    pub invested: i32,
+    pub kills: u32,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct CoinSample {
+    pub time: f64,
+    pub coins: i32,
+}
+
+pub fn load_scenario(path: &Path) -> anyhow::Result<Scenario> {
+    Ok(parse_ron(path, &fs::read(path)?)?)
+}
+
+/// How long the map and data files may take to load.
+const LOAD_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// An app that plays `map` without a window, one tick per update. The map is
+/// set up over the first updates, once the data files are loaded.
+pub fn headless_app(map: Map) -> App {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .add_plugin(AssetPlugin)
+        .add_plugin(GameplayPlugin)
+        .insert_resource(TicksPerUpdate(1));
+    let handle = app.world.resource_mut::<Assets<Map>>().add(map);
+    app.insert_resource(MapSource::Asset(handle))
+        .insert_resource(NextState(GameState::LoadingMap));
+    app
+}
+
+/// Plays `scenario` to the end as fast as possible. The result is the same
+/// whatever the number of ticks per update.
+pub fn run_scenario(
+    scenario: &Scenario,
+    ticks_per_update: u32,
+) -> anyhow::Result<SimulationResult> {
+    // Read like `save_map` writes, so the waves can be replaced before the map
+    // is set up.
+    let path = asset_path(&scenario.map);
+    let mut map: Map = parse_ron(&path, &fs::read(&path)?)?;
+    if let Some(waves) = &scenario.waves {
+        for lane in &mut map.lanes {
+            lane.waves = waves.clone();
+        }
+    }
+    let map_name = map.name.clone();
+
+    let mut app = headless_app(map);
+    app.insert_resource(TicksPerUpdate(ticks_per_update))
+        .insert_resource(BuildPlan {
+            steps: scenario.builds.clone(),
+            next: 0,
+        })
+        .insert_resource(TimeLimit(scenario.time_limit))
+        .init_resource::<CoinHistory>()
+        .add_simulation_system(SimulationPhase::Input, build_plan_run)
+        .add_simulation_system(SimulationPhase::Cleanup, coins_record)
+        .add_simulation_system(SimulationPhase::Cleanup, time_limit_check);
+
+    let started = Instant::now();
+    let outcome = loop {
+        app.update();
+        match app.world.resource::<CurrentState<GameState>>().0 {
+            GameState::Victory => break Outcome::Victory,
+            GameState::GameOver => break Outcome::Defeat,
+            GameState::Paused => break Outcome::Timeout,
+            GameState::LoadingMap if started.elapsed() > LOAD_TIMEOUT => {
+                bail!("Timed out loading map '{}'", scenario.map)
+            }
+            // Invalid maps are sent back to the menu.
+            GameState::MainMenu if !app.world.contains_resource::<NextState<GameState>>() => {
+                bail!("Could not set up map '{}'", scenario.map)
+            }
+            _ => {}
+        }
+    };
+
+    let world = &mut app.world;
+    let mut tower_query = world.query::<(&Tower, &GridPosition)>();
+    let mut base_query = world.query_filtered::<&Health, With<Base>>();
+    let tower_kinds = world
+        .get_resource::<TowerKinds>()
+        .ok_or_else(|| anyhow!("Tower kinds were not loaded"))?;
+    let towers = tower_query
+        .iter(world)
+        .map(|(tower, position)| TowerResult {
+            kind: tower_kinds[tower.kind].name.clone(),
+            position: **position,
+            invested: tower.invested,
+            kills: tower.kills,
+        })
+        .collect();
+    let base_health = base_query
+        .iter(world)
+        .next()
+        .map_or(0, |health| health.current.max(0));
+
+    Ok(SimulationResult {
+        map: map_name,
+        outcome,
+        duration: world.resource::<GameClock>().elapsed(),
+        base_health,
+        wave: world.resource::<CurrentWave>().number,
+        kills: world.resource::<GameStats>().kills,
+        towers,
+        coins: std::mem::take(&mut world.resource_mut::<CoinHistory>().0),
+    })
+}
+
+struct BuildPlan {
+    steps: Vec<BuildStep>,
+    /// Index of the step being waited on.
+    next: usize,
+}
+
+/// Sends the due steps of the plan, to be carried out on the next tick.
+fn build_plan_run(
+    mut plan: ResMut<BuildPlan>,
+    mut spawn_events: EventWriter<SpawnTower>,
+    mut upgrade_events: EventWriter<UpgradeTower>,
+    clock: Res<GameClock>,
+    currency: Res<Currency>,
+    tower_kinds: Res<TowerKinds>,
+    tower_query: Query<(Entity, &Tower, &TowerUpgrades, &GridPosition)>,
+) {
+    // Coins are only spent on the next tick, so at most one step can be
+    // afforded for sure.
+    let step = match plan.steps.get(plan.next) {
+        Some(step) if step.time() <= clock.elapsed() => step,
+        _ => return,
+    };
+    match step {
+        BuildStep::Build { tower, at, .. } => match tower_kinds.index_of(tower) {
+            Some(kind) if currency.coins < tower_kinds[kind].cost => return,
+            Some(kind) => spawn_events.send(SpawnTower {
+                position: *at,
+                kind,
+            }),
+            None => warn!("Skipping build of unknown tower kind '{}'", tower),
+        },
+        BuildStep::Upgrade { at, branch, .. } => {
+            let tower = tower_query
+                .iter()
+                .find(|(_, _, _, position)| ***position == *at);
+            let (entity, tower, upgrades, _) = match tower {
+                Some(tower) => tower,
+                None => {
+                    warn!("Skipping upgrade of missing tower at {}", at);
+                    plan.next += 1;
+                    return;
+                }
+            };
+            match upgrades.next(&tower_kinds[tower.kind], *branch) {
+                Some(level) if currency.coins < level.cost => return,
+                Some(_) => upgrade_events.send(UpgradeTower {
+                    tower: entity,
+                    branch: *branch,
+                }),
+                None => warn!("Skipping unavailable upgrade of tower at {}", at),
+            }
+        }
+    }
+    plan.next += 1;
+}
+
+#[derive(Default)]
+struct CoinHistory(Vec<CoinSample>);
+
+fn coins_record(clock: Res<GameClock>, currency: Res<Currency>, mut history: ResMut<CoinHistory>) {
+    let second = clock.elapsed().floor();
+    if history.0.last().map_or(true, |sample| sample.time < second) {
+        history.0.push(CoinSample {
+            time: second,
+            coins: currency.coins,
+        });
+    }
+}
+
+/// Seconds of game time after which the scenario is given up.
+struct TimeLimit(f64);
+
+/// Pauses at the time limit. No more ticks run once the state is about to
+/// change, so the run stops on the same tick whatever the ticks per update.
+fn time_limit_check(mut commands: Commands, clock: Res<GameClock>, limit: Res<TimeLimit>) {
+    if clock.elapsed() >= limit.0 {
+        commands.insert_resource(NextState(GameState::Paused));
+    }
+}
@@ -0,0 +1,47 @@
+//! The game as a library. [`GamePlugin`] is the whole game on top of
+//! `DefaultPlugins`, and [`GameplayPlugin`] the game without any window, input,
+//! audio or rendering, for tests and headless runs.
+
+pub mod audio;
+pub mod base;
+pub mod camera;
+pub mod clock;
+pub mod coord;
+pub mod currency;
+pub mod editor;
+pub mod enemy;
+pub mod enemy_kind;
+pub mod entity_render;
+pub mod field;
+pub mod game;
+pub mod game_state;
+pub mod generator;
+pub mod headless;
+pub mod health;
+pub mod map;
+pub mod map_render;
+pub mod menu;
+pub mod mesh;
+pub mod path;
+pub mod projectile;
+pub mod records;
+pub mod ron_asset;
+pub mod simulation;
+pub mod stats;
+pub mod targeting;
+pub mod tile_map;
+pub mod tower;
+pub mod tower_input;
+pub mod tower_kind;
+pub mod ui;
+pub mod validation;
+pub mod wave;
+
+pub use crate::{
+    coord::Coord,
+    currency::Currency,
+    game::{GamePlugin, GameplayPlugin},
+    health::Health,
+    mesh::RegPoly,
+    path::Path,
+};
@@ -2,27 +2,94 @@ use bevy::prelude::*;
 use bevy_egui::EguiPlugin;
 use bevy_kira_audio::AudioPlugin;
 
-use crate::game::GamePlugin;
-
-mod audio;
-mod base;
-mod coord;
-mod currency;
-mod enemy;
-mod game;
-mod game_state;
-mod health;
-mod map;
-mod mesh;
-mod projectile;
-mod tower;
-mod ui;
+use iyes_loopless::prelude::*;
+
+use std::path::Path;
+
+use tower_defense::{
+    game::GamePlugin,
+    game_state::GameState,
+    generator::GeneratorSettings,
+    headless::{load_scenario, run_scenario},
+    map::MapSource,
+};
 
 fn main() {
-    App::new()
-        .add_plugins(DefaultPlugins)
+    let mut args = std::env::args().skip(1).peekable();
+    if args.peek().map(String::as_str) == Some("simulate") {
+        std::process::exit(simulate(args.skip(1)));
+    }
+
+    let settings = match generator_settings_from_args(args) {
+        Ok(settings) => settings,
+        Err(err) => {
+            eprintln!("{}", err);
+            eprintln!("Usage: tower-defense [--seed <number>] [--difficulty easy|normal|hard]");
+            eprintln!("       tower-defense simulate <scenario.ron>...");
+            std::process::exit(2);
+        }
+    };
+
+    let mut app = App::new();
+    // A generated map from the command line skips the main menu.
+    if let Some(settings) = settings {
+        app.insert_resource(MapSource::Generated(settings))
+            .insert_resource(NextState(GameState::LoadingMap));
+    }
+    app.add_plugins(DefaultPlugins)
         .add_plugin(EguiPlugin)
         .add_plugin(AudioPlugin)
         .add_plugin(GamePlugin)
         .run();
 }
+
+/// Plays every scenario file without a window and prints one JSON result per
+/// line. Returns the exit code.
+fn simulate(paths: impl Iterator<Item = String>) -> i32 {
+    let mut code = 0;
+    for path in paths {
+        match load_scenario(Path::new(&path)).and_then(|scenario| run_scenario(&scenario, 1)) {
+            Ok(result) => match serde_json::to_string(&result) {
+                Ok(json) => println!("{}", json),
+                Err(err) => {
+                    eprintln!("{}: {}", path, err);
+                    code = 1;
+                }
+            },
+            Err(err) => {
+                eprintln!("{}: {}", path, err);
+                code = 1;
+            }
+        }
+    }
+    code
+}
+
+/// Settings for a generated map if `--seed` or `--difficulty` is given.
+/// Without a seed, a random one is picked.
+fn generator_settings_from_args(
+    mut args: impl Iterator<Item = String>,
+) -> Result<Option<GeneratorSettings>, String> {
+    let mut settings: Option<GeneratorSettings> = None;
+    while let Some(arg) = args.next() {
+        if !matches!(arg.as_str(), "--seed" | "--difficulty") {
+            return Err(format!("Unknown argument '{}'", arg));
+        }
+        let value = args
+            .next()
+            .ok_or_else(|| format!("Missing value for {}", arg))?;
+        let settings = settings.get_or_insert_with(|| GeneratorSettings {
+            seed: rand::random(),
+            ..default()
+        });
+        match arg.as_str() {
+            "--seed" => {
+                settings.seed = value
+                    .parse()
+                    .map_err(|_| format!("Invalid seed '{}'", value))?;
+            }
+            _ => settings.difficulty = value.parse()?,
+        }
+    }
+    Ok(settings)
+}
@@ -1,80 +1,338 @@
-use bevy::prelude::*;
+use bevy::{asset::LoadState, prelude::*, reflect::TypeUuid};
 use iyes_loopless::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use std::{collections::HashSet, fs, sync::Arc};
 
 use crate::{
     base::SpawnBase,
+    clock::GameClock,
     coord::Coord,
-    enemy::{Path, PlayTime, SpawnEnemySpawner},
+    currency::Currency,
+    enemy::SpawnEnemySpawner,
+    enemy_kind::enemy_kinds_loaded,
+    field::OpenField,
     game_state::GameState,
-    tower::SpawnBuildSpot,
+    generator::{generate_map, Difficulty, GeneratorSettings},
+    path::{BranchChoice, Path},
+    ron_asset::{asset_path, RonAssetLoader},
+    simulation::SimulationRng,
+    stats::GameStats,
+    tile_map::{Bounds, Terrain, TileMap},
+    tower::{SellRefund, SpawnBuildSpot},
+    tower_kind::tower_kinds_loaded,
+    validation::validate_map,
+    wave::{CurrentWave, Wave},
 };
 
 pub struct MapPlugin;
 
 impl Plugin for MapPlugin {
     fn build(&self, app: &mut App) {
-        app.add_enter_system(GameState::LoadingMap, map_setup);
+        app.add_asset::<Map>()
+            .add_asset_loader(RonAssetLoader::<Map>::new(&["map.ron"]))
+            .init_resource::<MapSource>()
+            .add_enter_system(GameState::LoadingMap, map_teardown)
+            .add_enter_system(GameState::Editor, map_teardown)
+            .add_enter_system(GameState::MainMenu, map_teardown)
+            .add_enter_system(GameState::LoadingMap, map_load)
+            .add_system(
+                map_setup
+                    .run_in_state(GameState::LoadingMap)
+                    .run_if(tower_kinds_loaded)
+                    .run_if(enemy_kinds_loaded),
+            );
     }
 }
 
-struct Map<'a> {
-    path: &'a [Coord],
-    build_spots: &'a [Coord],
+/// A level, as stored in `assets/maps/*.map.ron`.
+#[derive(Clone, Deserialize, Serialize, TypeUuid)]
+#[uuid = "11a753a4-f7d7-43c0-acd3-1dcb32a0dab4"]
+pub struct Map {
+    pub name: String,
+    /// Shown in the level select screen.
+    #[serde(default)]
+    pub difficulty: Difficulty,
+    pub starting_coins: i32,
+    #[serde(default)]
+    pub sell_refund: SellRefund,
+    pub base: Coord,
+    /// Each lane has its own spawner at the start of its path. Lanes may share
+    /// nodes, for example to merge before reaching the base.
+    pub lanes: Vec<Lane>,
+    #[serde(default)]
+    pub build_spots: Vec<Coord>,
+    /// Turns the map into an open field: the area becomes buildable, and
+    /// enemies find their own way around towers from the first node of their
+    /// lane's path to the base.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub open_field: Option<Bounds>,
+    /// Terrain painted over everything else, in order.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub terrain: Vec<TerrainArea>,
 }
 
-const MAP: Map = Map {
-    path: &[
-        Coord::new(0, 0),
-        Coord::new(1, 0),
-        Coord::new(1, -2),
-        Coord::new(-2, -2),
-        Coord::new(-2, 2),
-        Coord::new(3, 2),
-        Coord::new(3, -4),
-        Coord::new(-2, -4),
-    ],
-    build_spots: &[
-        Coord::new(0, -1),
-        Coord::new(-1, -1),
-        Coord::new(-1, 0),
-        Coord::new(-1, 1),
-        Coord::new(0, 1),
-        Coord::new(1, 1),
-        Coord::new(2, 1),
-        Coord::new(2, 0),
-        Coord::new(2, -1),
-        Coord::new(2, -2),
-        Coord::new(2, -3),
-        Coord::new(1, -3),
-        Coord::new(0, -3),
-        Coord::new(-1, -3),
-        Coord::new(-2, -3),
-    ],
-};
+#[derive(Clone, Deserialize, Serialize)]
+pub struct TerrainArea {
+    pub terrain: Terrain,
+    pub min: Coord,
+    pub max: Coord,
+}
+
+impl Map {
+    /// Paths, build spots, the open field and terrain areas rasterized onto a
+    /// grid with a one cell margin of blocked terrain.
+    pub fn tile_map(&self) -> TileMap {
+        let path_nodes = || {
+            self.lanes.iter().flat_map(|lane| {
+                lane.path
+                    .iter()
+                    .chain(lane.branches.iter().flat_map(|branch| &branch.path))
+                    .copied()
+            })
+        };
+        let areas = self
+            .open_field
+            .iter()
+            .copied()
+            .chain(self.terrain.iter().map(|area| Bounds {
+                min: area.min,
+                max: area.max,
+            }));
+        let coords = path_nodes()
+            .chain(self.build_spots.iter().copied())
+            .chain(areas.flat_map(|area| [area.min, area.max]))
+            .chain([self.base]);
+        let bounds = Bounds::around(coords).unwrap_or(Bounds {
+            min: self.base,
+            max: self.base,
+        });
+
+        let mut tile_map = TileMap::new(bounds.grow(1), Terrain::Blocked);
+        if let Some(open_field) = self.open_field {
+            tile_map.fill(open_field, Terrain::Buildable);
+        }
+        for &build_spot in &self.build_spots {
+            tile_map.set(build_spot, Terrain::Buildable);
+        }
+        for lane in &self.lanes {
+            tile_map.draw_path(&lane.path);
+            for branch in &lane.branches {
+                tile_map.draw_path(&branch.path);
+            }
+        }
+        tile_map.set(self.base, Terrain::Path);
+        for area in &self.terrain {
+            let bounds = Bounds {
+                min: area.min,
+                max: area.max,
+            };
+            tile_map.fill(bounds, area.terrain);
+        }
+        tile_map
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Lane {
+    pub path: Vec<Coord>,
+    /// Alternative routes forking off the path.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub branches: Vec<Branch>,
+    /// How enemies pick a route at forks.
+    #[serde(default)]
+    pub choice: BranchChoice,
+    /// Waves of every lane are numbered together: wave `n` of each lane is part
+    /// of the same overall wave `n`.
+    pub waves: Vec<Wave>,
+}
+
+/// A route that forks off a lane's path at its first node, and merges back
+/// into it wherever it meets it again.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Branch {
+    pub path: Vec<Coord>,
+    /// Relative chance of taking the branch, compared to the lane's path which
+    /// has a weight of 1.
+    #[serde(default = "default_branch_weight")]
+    pub weight: f32,
+}
+
+fn default_branch_weight() -> f32 {
+    1.0
+}
+
+/// Marks every entity that belongs to the current map, so that it can be
+/// cleared before the map is set up again.
+#[derive(Component)]
+pub struct MapEntity;
+
+fn map_teardown(mut commands: Commands, query: Query<Entity, With<MapEntity>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+pub const MAP_PATH: &str = "maps/level1.map.ron";
+
+/// Where maps that do not come from a file are saved when they are edited.
+pub const GENERATED_MAP_PATH: &str = "maps/generated.map.ron";
+
+/// The map to set up when entering [`GameState::LoadingMap`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MapSource {
+    /// A map file, relative to the assets directory.
+    File(String),
+    Generated(GeneratorSettings),
+    /// A map added to [`Assets<Map>`] directly.
+    Asset(Handle<Map>),
+}
+
+impl MapSource {
+    /// The file the map is saved to when edited.
+    pub fn path(&self) -> &str {
+        match self {
+            MapSource::File(path) => path,
+            MapSource::Generated(_) | MapSource::Asset(_) => GENERATED_MAP_PATH,
+        }
+    }
+}
+
+impl Default for MapSource {
+    fn default() -> Self {
+        MapSource::File(MAP_PATH.to_string())
+    }
+}
+
+pub struct MapHandle(pub Handle<Map>);
+
+/// Writes `map` to `path` within the assets directory.
+pub fn save_map(map: &Map, path: &str) -> anyhow::Result<()> {
+    let text = ron::ser::to_string_pretty(map, ron::ser::PrettyConfig::new())?;
+    fs::write(asset_path(path), text)?;
+    Ok(())
+}
+
+fn map_load(
+    mut commands: Commands,
+    mut maps: ResMut<Assets<Map>>,
+    asset_server: Res<AssetServer>,
+    source: Res<MapSource>,
+) {
+    let handle = match &*source {
+        MapSource::File(path) => asset_server.load(path.as_str()),
+        MapSource::Generated(settings) => maps.add(generate_map(settings)),
+        MapSource::Asset(handle) => handle.clone(),
+    };
+    commands.insert_resource(MapHandle(handle));
+}
 
 pub fn map_setup(
     mut commands: Commands,
+    mut currency: ResMut<Currency>,
+    mut clock: ResMut<GameClock>,
     mut build_spot_spawn_events: EventWriter<SpawnBuildSpot>,
     mut enemy_spawner_spawn_events: EventWriter<SpawnEnemySpawner>,
     mut base_spawn_events: EventWriter<SpawnBase>,
+    asset_server: Res<AssetServer>,
+    maps: Res<Assets<Map>>,
+    source: Res<MapSource>,
+    map_handle: Option<Res<MapHandle>>,
 ) {
-    enemy_spawner_spawn_events.send(SpawnEnemySpawner {
-        position: MAP.path[0],
-    });
+    let map_handle = match map_handle {
+        Some(map_handle) => map_handle,
+        None => return,
+    };
+    let map = match maps.get(&map_handle.0) {
+        Some(map) => map,
+        None => {
+            if asset_server.get_load_state(&map_handle.0) == LoadState::Failed {
+                // The asset server has already logged the parse error itself.
+                error!("Could not load map '{}'", source.path());
+                commands.remove_resource::<MapHandle>();
+                commands.insert_resource(NextState(GameState::MainMenu));
+            }
+            return;
+        }
+    };
+
+    let problems = validate_map(map);
+    if !problems.is_empty() {
+        for problem in &problems {
+            error!("{}", problem);
+        }
+        error!("Could not set up invalid map '{}'", map.name);
+        commands.remove_resource::<MapHandle>();
+        commands.insert_resource(NextState(GameState::MainMenu));
+        return;
+    }
+
+    info!("Loaded map '{}'", map.name);
 
-    base_spawn_events.send(SpawnBase {
-        position: *MAP
-            .path
-            .last()
-            .expect("Path must have at least one segment"),
+    let tile_map = map.tile_map();
+    let field = map.open_field.map(|_| OpenField {
+        base: map.base,
+        entrances: map
+            .lanes
+            .iter()
+            .filter_map(|lane| lane.path.first().copied())
+            .collect(),
     });
 
-    commands.insert_resource(Path::new(Vec::from(MAP.path)));
+    for lane in &map.lanes {
+        let path = match &field {
+            Some(field) => match field.route(&tile_map, lane.path[0], &HashSet::new()) {
+                Some(route) => Path::new(&route),
+                None => {
+                    warn!(
+                        "Skipping lane that cannot reach the base in map '{}'",
+                        map.name
+                    );
+                    continue;
+                }
+            },
+            None => {
+                let mut path = Path::new(&lane.path);
+                path.choice = lane.choice;
+                for branch in &lane.branches {
+                    path.add_branch(&branch.path, branch.weight);
+                }
+                path
+            }
+        };
+        enemy_spawner_spawn_events.send(SpawnEnemySpawner {
+            path: Arc::new(path),
+            waves: lane.waves.clone(),
+        });
+    }
+
+    base_spawn_events.send(SpawnBase { position: map.base });
 
-    for &position in MAP.build_spots {
-        build_spot_spawn_events.send(SpawnBuildSpot { position });
+    for (position, terrain) in tile_map.iter() {
+        if terrain.is_buildable() {
+            build_spot_spawn_events.send(SpawnBuildSpot { position });
+        }
+    }
+    commands.insert_resource(tile_map);
+    match field {
+        Some(field) => commands.insert_resource(field),
+        None => commands.remove_resource::<OpenField>(),
     }
 
+    currency.coins = map.starting_coins;
+    commands.insert_resource(map.sell_refund);
+
     commands.insert_resource(NextState(GameState::Playing));
-    commands.insert_resource(PlayTime { seconds: 0.0 });
+    clock.reset();
+    commands.insert_resource(SimulationRng::default());
+    commands.insert_resource(GameStats::default());
+    commands.insert_resource(CurrentWave {
+        total: map
+            .lanes
+            .iter()
+            .map(|lane| lane.waves.len())
+            .max()
+            .unwrap_or(0),
+        ..default()
+    });
 }
@@ -0,0 +1,202 @@
+use bevy::{prelude::*, sprite::Mesh2dHandle};
+use bevy_egui::EguiContext;
+use iyes_loopless::prelude::*;
+
+use std::sync::Arc;
+
+use crate::{
+    camera::CursorPosition,
+    coord::{CELL_SIZE, HALF_CELL_SIZE},
+    enemy::EnemySpawner,
+    game_state::GameState,
+    map::MapEntity,
+    mesh::{Grid, MeshMaterial, RegPoly, Track},
+    path::Path,
+    tile_map::TileMap,
+};
+
+pub struct MapRenderPlugin;
+
+impl Plugin for MapRenderPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GridOverlay>()
+            .add_startup_system(map_render_setup)
+            .add_system_set(
+                ConditionSet::new()
+                    .run_in_state(GameState::Playing)
+                    .with_system(track_spawn)
+                    .with_system(grid_spawn)
+                    .with_system(grid_toggle)
+                    .into(),
+            )
+            .add_system(cursor_highlight);
+    }
+}
+
+/// Whether the faint cell grid is drawn over the map. Toggled with G.
+#[derive(Default)]
+pub struct GridOverlay(pub bool);
+
+const TRACK_Z: f32 = 0.1;
+const ARROW_Z: f32 = 0.15;
+const GRID_Z: f32 = 0.2;
+const HIGHLIGHT_Z: f32 = 0.25;
+
+struct MapRenderAssets {
+    track: Handle<ColorMaterial>,
+    arrow: MeshMaterial,
+    grid: Handle<ColorMaterial>,
+}
+
+#[derive(Component)]
+struct TrackPiece;
+
+#[derive(Component)]
+struct GridLines;
+
+#[derive(Component)]
+struct CursorHighlight;
+
+fn map_render_setup(
+    mut commands: Commands,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    commands.insert_resource(MapRenderAssets {
+        track: materials.add(Color::rgb(0.22, 0.2, 0.17).into()),
+        arrow: MeshMaterial {
+            mesh: Mesh2dHandle(meshes.add(RegPoly::fill(3, 5.0).into())),
+            material: materials.add(Color::rgb(0.36, 0.33, 0.27).into()),
+        },
+        grid: materials.add(Color::rgba(1.0, 1.0, 1.0, 0.06).into()),
+    });
+
+    commands
+        .spawn_bundle(ColorMesh2dBundle {
+            mesh: Mesh2dHandle(meshes.add(shape::Quad::new(Vec2::splat(CELL_SIZE)).into())),
+            material: materials.add(Color::rgba(1.0, 1.0, 1.0, 0.12).into()),
+            visibility: Visibility { is_visible: false },
+            ..Default::default()
+        })
+        .insert(CursorHighlight);
+}
+
+/// Redraws the track whenever a spawner's path is replaced, which happens
+/// when a map is set up and when enemies repath in an open field.
+fn track_spawn(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut shown: Local<Vec<Arc<Path>>>,
+    assets: Res<MapRenderAssets>,
+    spawner_query: Query<&EnemySpawner>,
+    track_query: Query<Entity, With<TrackPiece>>,
+) {
+    let paths: Vec<Arc<Path>> = spawner_query
+        .iter()
+        .map(|spawner| Arc::clone(&spawner.path))
+        .collect();
+    if paths.len() == shown.len() && paths.iter().zip(&*shown).all(|(a, b)| Arc::ptr_eq(a, b)) {
+        return;
+    }
+
+    for entity in track_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    let segments: Vec<(Vec2, Vec2)> = paths.iter().flat_map(|path| path.segments()).collect();
+    for &(start, end) in &segments {
+        let direction = end - start;
+        let midpoint = (start + end) * 0.5;
+        commands
+            .spawn_bundle(ColorMesh2dBundle {
+                mesh: assets.arrow.mesh.clone(),
+                material: assets.arrow.material.clone(),
+                transform: Transform::from_translation(midpoint.extend(ARROW_Z))
+                    .with_rotation(Quat::from_rotation_z(direction.y.atan2(direction.x))),
+                ..Default::default()
+            })
+            .insert(TrackPiece)
+            .insert(MapEntity);
+    }
+    let track = Track {
+        segments,
+        width: CELL_SIZE * 0.75,
+    };
+    commands
+        .spawn_bundle(ColorMesh2dBundle {
+            mesh: Mesh2dHandle(meshes.add(track.into())),
+            material: assets.track.clone(),
+            transform: Transform::from_xyz(0.0, 0.0, TRACK_Z),
+            ..Default::default()
+        })
+        .insert(TrackPiece)
+        .insert(MapEntity);
+
+    *shown = paths;
+}
+
+fn grid_spawn(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    assets: Res<MapRenderAssets>,
+    overlay: Res<GridOverlay>,
+    tile_map: Res<TileMap>,
+    query: Query<Entity, With<GridLines>>,
+) {
+    if !overlay.is_changed() && !tile_map.is_changed() {
+        return;
+    }
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    if !overlay.0 {
+        return;
+    }
+
+    let bounds = tile_map.bounds();
+    let grid = Grid {
+        min: Vec2::from(bounds.min) - HALF_CELL_SIZE,
+        max: Vec2::from(bounds.max) + HALF_CELL_SIZE,
+        spacing: CELL_SIZE,
+    };
+    commands
+        .spawn_bundle(ColorMesh2dBundle {
+            mesh: Mesh2dHandle(meshes.add(grid.into())),
+            material: assets.grid.clone(),
+            transform: Transform::from_xyz(0.0, 0.0, GRID_Z),
+            ..Default::default()
+        })
+        .insert(GridLines)
+        .insert(MapEntity);
+}
+
+fn grid_toggle(
+    mut egui_ctx: ResMut<EguiContext>,
+    keys: Res<Input<KeyCode>>,
+    mut overlay: ResMut<GridOverlay>,
+) {
+    if keys.just_pressed(KeyCode::G) && !egui_ctx.ctx_mut().wants_keyboard_input() {
+        overlay.0 = !overlay.0;
+    }
+}
+
+/// Highlights the cell under the cursor while playing, and anywhere in the
+/// editor.
+fn cursor_highlight(
+    cursor: Res<CursorPosition>,
+    state: Res<CurrentState<GameState>>,
+    tile_map: Option<Res<TileMap>>,
+    mut query: Query<(&mut Transform, &mut Visibility), With<CursorHighlight>>,
+) {
+    let coord = match (&state.0, tile_map) {
+        (GameState::Playing, Some(tile_map)) => tile_map.cursor_coord(&cursor),
+        (GameState::Editor, _) => cursor.coord(),
+        _ => None,
+    };
+    for (mut transform, mut visibility) in query.iter_mut() {
+        visibility.is_visible = coord.is_some();
+        if let Some(coord) = coord {
+            transform.translation = Vec2::from(coord).extend(HIGHLIGHT_Z);
+        }
+    }
+}
@@ -0,0 +1,194 @@
+use bevy::{app::AppExit, asset::LoadState, prelude::*};
+use bevy_egui::{egui, EguiContext};
+use iyes_loopless::prelude::*;
+
+use crate::{
+    audio::GlobalVolume,
+    game_state::GameState,
+    generator::{Difficulty, GeneratorSettings},
+    map::{Map, MapSource, MAP_PATH},
+    map_render::GridOverlay,
+    records::Records,
+    validation::validate_map,
+};
+
+pub struct MenuPlugin;
+
+impl Plugin for MenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MenuScreen>()
+            .add_startup_system(level_list_load)
+            .add_enter_system(GameState::MainMenu, menu_enter)
+            .add_system(main_menu.run_in_state(GameState::MainMenu));
+    }
+}
+
+/// The page of the main menu being shown.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MenuScreen {
+    #[default]
+    Main,
+    LevelSelect,
+    Settings,
+}
+
+/// Every map file in `assets/maps`.
+pub struct LevelList(Vec<Handle<Map>>);
+
+fn level_list_load(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let handles = match asset_server.load_folder("maps") {
+        Ok(handles) => handles
+            .into_iter()
+            .map(|handle| handle.typed::<Map>())
+            .collect(),
+        Err(err) => {
+            error!("Could not list maps: {}", err);
+            Vec::new()
+        }
+    };
+    commands.insert_resource(LevelList(handles));
+}
+
+fn menu_enter(mut screen: ResMut<MenuScreen>) {
+    *screen = MenuScreen::Main;
+}
+
+fn main_menu(
+    mut commands: Commands,
+    mut egui_ctx: ResMut<EguiContext>,
+    mut screen: ResMut<MenuScreen>,
+    mut source: ResMut<MapSource>,
+    mut volume: ResMut<GlobalVolume>,
+    mut grid_overlay: ResMut<GridOverlay>,
+    mut difficulty: Local<Difficulty>,
+    mut exit_events: EventWriter<AppExit>,
+    asset_server: Res<AssetServer>,
+    maps: Res<Assets<Map>>,
+    levels: Res<LevelList>,
+    records: Res<Records>,
+) {
+    let current = *screen;
+    let title = match current {
+        MenuScreen::Main => "Tower Defense",
+        MenuScreen::LevelSelect => "Level Select",
+        MenuScreen::Settings => "Settings",
+    };
+
+    egui::Window::new(title)
+        .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+        .collapsible(false)
+        .resizable(false)
+        .show(egui_ctx.ctx_mut(), |ui| match current {
+            MenuScreen::Main => {
+                ui.vertical_centered_justified(|ui| {
+                    if ui.button("Play").clicked() {
+                        *source = MapSource::File(MAP_PATH.to_string());
+                        commands.insert_resource(NextState(GameState::LoadingMap));
+                    }
+                    if ui.button("Level Select").clicked() {
+                        *screen = MenuScreen::LevelSelect;
+                    }
+                    ui.horizontal(|ui| {
+                        if ui.button("Random map").clicked() {
+                            *source = MapSource::Generated(GeneratorSettings {
+                                seed: rand::random(),
+                                difficulty: *difficulty,
+                                ..default()
+                            });
+                            commands.insert_resource(NextState(GameState::LoadingMap));
+                        }
+                        egui::ComboBox::from_id_source("random_map_difficulty")
+                            .selected_text(difficulty.name())
+                            .show_ui(ui, |ui| {
+                                for option in Difficulty::ALL {
+                                    ui.selectable_value(&mut *difficulty, option, option.name());
+                                }
+                            });
+                    });
+                    if ui.button("Settings").clicked() {
+                        *screen = MenuScreen::Settings;
+                    }
+                    if ui.button("Quit").clicked() {
+                        exit_events.send(AppExit);
+                    }
+                });
+            }
+            MenuScreen::LevelSelect => {
+                egui::Grid::new("level_grid").striped(true).show(ui, |ui| {
+                    ui.strong("Map");
+                    ui.strong("Difficulty");
+                    ui.strong("Best");
+                    ui.end_row();
+
+                    // Maps that cannot be played are listed with the reason,
+                    // so they can be fixed.
+                    let mut levels: Vec<(String, Result<&Map, String>)> = levels
+                        .0
+                        .iter()
+                        .filter_map(|handle| {
+                            let path = asset_server.get_handle_path(handle)?;
+                            let path = path.path().to_string_lossy().replace('\\', "/");
+                            let level = match maps.get(handle) {
+                                Some(map) => match validate_map(map).first() {
+                                    Some(problem) => Err(problem.to_string()),
+                                    None => Ok(map),
+                                },
+                                None if asset_server.get_load_state(handle)
+                                    == LoadState::Failed =>
+                                {
+                                    Err("Could not be loaded".to_string())
+                                }
+                                // Still loading.
+                                None => return None,
+                            };
+                            Some((path, level))
+                        })
+                        .collect();
+                    levels.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+                    for (path, level) in levels {
+                        let map = match level {
+                            Ok(map) => map,
+                            Err(error) => {
+                                ui.weak(&path);
+                                ui.colored_label(egui::Color32::from_rgb(200, 30, 30), error);
+                                ui.end_row();
+                                continue;
+                            }
+                        };
+                        let selected = *source == MapSource::File(path.clone());
+                        if ui.selectable_label(selected, &map.name).clicked() {
+                            *source = MapSource::File(path.clone());
+                            commands.insert_resource(NextState(GameState::LoadingMap));
+                        }
+                        ui.label(map.difficulty.name());
+                        match records.0.get(&path) {
+                            Some(record) => ui.label(record.to_string()),
+                            None => ui.weak("-"),
+                        };
+                        ui.end_row();
+                    }
+                });
+
+                ui.separator();
+
+                if ui.button("Back").clicked() {
+                    *screen = MenuScreen::Main;
+                }
+            }
+            MenuScreen::Settings => {
+                ui.add(egui::Slider::new(&mut volume.0, 0..=100).text("Volume"));
+                let mut show_grid = grid_overlay.0;
+                ui.checkbox(&mut show_grid, "Show grid (G)");
+                if show_grid != grid_overlay.0 {
+                    grid_overlay.0 = show_grid;
+                }
+
+                ui.separator();
+
+                if ui.button("Back").clicked() {
+                    *screen = MenuScreen::Main;
+                }
+            }
+        });
+}
@@ -118,3 +118,84 @@ impl From<RegPoly> for Mesh {
         }
     }
 }
+
+/// A strip of constant width along straight segments. Each segment is
+/// extended by half the width at both ends so that corners are filled.
+pub struct Track {
+    pub segments: Vec<(Vec2, Vec2)>,
+    pub width: f32,
+}
+
+impl From<Track> for Mesh {
+    fn from(track: Track) -> Self {
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+
+        let half_width = track.width * 0.5;
+        let mut positions = Vec::with_capacity(track.segments.len() * 4);
+        for &(start, end) in &track.segments {
+            let along = (end - start).normalize_or_zero() * half_width;
+            let across = along.perp();
+            for corner in [
+                start - along - across,
+                end + along - across,
+                end + along + across,
+                start - along + across,
+            ] {
+                positions.push([corner.x, corner.y, 0.0]);
+            }
+        }
+
+        let normals = vec![[0.0, 0.0, 1.0]; positions.len()];
+        let uvs = vec![[0.0, 0.0]; positions.len()];
+
+        let mut indices = Vec::with_capacity(track.segments.len() * 6);
+        for i in 0..track.segments.len() as u32 {
+            let first = i * 4;
+            indices.extend([first, first + 1, first + 2, first, first + 2, first + 3]);
+        }
+
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+        mesh.set_indices(Some(Indices::U32(indices)));
+        mesh
+    }
+}
+
+/// Lines between the cells of a grid covering `min` to `max`, which are cell
+/// corners.
+pub struct Grid {
+    pub min: Vec2,
+    pub max: Vec2,
+    pub spacing: f32,
+}
+
+impl From<Grid> for Mesh {
+    fn from(grid: Grid) -> Self {
+        let mut mesh = Mesh::new(PrimitiveTopology::LineList);
+
+        let columns = ((grid.max.x - grid.min.x) / grid.spacing).round() as u32;
+        let rows = ((grid.max.y - grid.min.y) / grid.spacing).round() as u32;
+        let mut positions = Vec::with_capacity((columns + rows + 2) as usize * 2);
+        for column in 0..=columns {
+            let x = grid.min.x + column as f32 * grid.spacing;
+            positions.push([x, grid.min.y, 0.0]);
+            positions.push([x, grid.max.y, 0.0]);
+        }
+        for row in 0..=rows {
+            let y = grid.min.y + row as f32 * grid.spacing;
+            positions.push([grid.min.x, y, 0.0]);
+            positions.push([grid.max.x, y, 0.0]);
+        }
+
+        let normals = vec![[0.0, 0.0, 1.0]; positions.len()];
+        let uvs = vec![[0.0, 0.0]; positions.len()];
+        let indices = (0..positions.len() as u32).collect();
+
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+        mesh.set_indices(Some(Indices::U32(indices)));
+        mesh
+    }
+}
@@ -0,0 +1,427 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::coord::Coord;
+
+/// How enemies pick a branch where a path forks.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub enum BranchChoice {
+    /// At random, in proportion to the weight of each branch.
+    #[default]
+    Weighted,
+    /// The branch covered the least by towers.
+    LeastDefended,
+}
+
+/// A one-way connection between two nodes of a [`Path`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Edge {
+    pub to: usize,
+    /// Relative chance of taking this edge with [`BranchChoice::Weighted`].
+    pub weight: f32,
+    /// Length in world units.
+    pub length: f32,
+}
+
+/// A directed graph through grid coordinates that enemies walk along. It
+/// starts at its first node and ends at every node without outgoing edges.
+/// Branches may fork off and merge back into it.
+pub struct Path {
+    nodes: Vec<Coord>,
+    /// Outgoing edges of each node.
+    edges: Vec<Vec<Edge>>,
+    /// Shortest distance from each node to an end, in world units.
+    to_end: Vec<f32>,
+    pub choice: BranchChoice,
+}
+
+/// Where an enemy is on a [`Path`].
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PathPosition {
+    /// The last node passed.
+    node: usize,
+    /// Index of the outgoing edge of `node` being walked, `None` while
+    /// standing on `node` before a branch has been picked.
+    edge: Option<usize>,
+    /// Distance travelled along `edge`, in world units.
+    distance: f32,
+}
+
+impl Path {
+    /// A path without forks through `nodes`.
+    pub fn new(nodes: &[Coord]) -> Path {
+        let mut path = Self {
+            nodes: Vec::new(),
+            edges: Vec::new(),
+            to_end: Vec::new(),
+            choice: BranchChoice::default(),
+        };
+        if let Some(&first) = nodes.first() {
+            path.node_index_or_insert(first);
+            path.add_branch(nodes, 1.0);
+        }
+        path
+    }
+
+    /// Adds a branch through `nodes`, which must start at a node already on
+    /// the path. It merges back into the path wherever it reaches one of its
+    /// nodes. Returns `false`, without changing the path, if the branch does
+    /// not start on the path.
+    pub fn add_branch(&mut self, nodes: &[Coord], weight: f32) -> bool {
+        let mut from = match nodes.first().and_then(|&first| self.node_index(first)) {
+            Some(from) => from,
+            None => return false,
+        };
+        for &coord in &nodes[1..] {
+            let to = self.node_index_or_insert(coord);
+            if to == from {
+                continue;
+            }
+            if !self.edges[from].iter().any(|edge| edge.to == to) {
+                let length = Vec2::from(self.nodes[from]).distance(coord.into());
+                self.edges[from].push(Edge { to, weight, length });
+            }
+            from = to;
+        }
+        self.update_distances();
+        true
+    }
+
+    pub fn start(&self) -> PathPosition {
+        PathPosition::default()
+    }
+
+    pub fn node_index(&self, coord: Coord) -> Option<usize> {
+        self.nodes.iter().position(|&node| node == coord)
+    }
+
+    fn node_index_or_insert(&mut self, coord: Coord) -> usize {
+        self.node_index(coord).unwrap_or_else(|| {
+            self.nodes.push(coord);
+            self.edges.push(Vec::new());
+            self.to_end.push(0.0);
+            self.nodes.len() - 1
+        })
+    }
+
+    fn update_distances(&mut self) {
+        // Nodes that cannot reach an end keep an infinite distance.
+        for (to_end, edges) in self.to_end.iter_mut().zip(&self.edges) {
+            *to_end = if edges.is_empty() { 0.0 } else { f32::INFINITY };
+        }
+        // The graphs are tiny, so relaxing every edge until nothing changes
+        // is fast enough.
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for node in 0..self.nodes.len() {
+                for edge in &self.edges[node] {
+                    let distance = edge.length + self.to_end[edge.to];
+                    if distance < self.to_end[node] {
+                        self.to_end[node] = distance;
+                        changed = true;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Moves `position` forward by `distance`, across as many nodes as needed.
+    /// Whenever it reaches a fork, `choose` is given the fork's node and its
+    /// outgoing edges and returns the index of the edge to take. Returns `true`
+    /// once the position has reached an end of the path.
+    pub fn advance(
+        &self,
+        position: &mut PathPosition,
+        distance: f32,
+        mut choose: impl FnMut(usize, &[Edge]) -> usize,
+    ) -> bool {
+        if self.nodes.is_empty() {
+            return true;
+        }
+        position.distance += distance;
+        loop {
+            let edges = &self.edges[position.node];
+            let edge = match position.edge {
+                Some(edge) => edge,
+                None => match edges.len() {
+                    0 => {
+                        position.distance = 0.0;
+                        return true;
+                    }
+                    1 => 0,
+                    _ => choose(position.node, edges).min(edges.len() - 1),
+                },
+            };
+            position.edge = Some(edge);
+
+            let edge = edges[edge];
+            if position.distance < edge.length {
+                return false;
+            }
+            position.distance -= edge.length;
+            position.node = edge.to;
+            position.edge = None;
+        }
+    }
+
+    fn edge(&self, position: &PathPosition) -> Option<Edge> {
+        position.edge.map(|edge| self.edges[position.node][edge])
+    }
+
+    /// The last node passed at `position`.
+    pub fn node_at(&self, position: &PathPosition) -> Option<Coord> {
+        self.nodes.get(position.node).copied()
+    }
+
+    /// The nodes at both ends of the edge being walked at `position`, and the
+    /// distance travelled along it.
+    pub fn edge_at(&self, position: &PathPosition) -> Option<(Coord, Coord, f32)> {
+        self.edge(position).map(|edge| {
+            (
+                self.nodes[position.node],
+                self.nodes[edge.to],
+                position.distance,
+            )
+        })
+    }
+
+    /// World position of `position`.
+    pub fn sample(&self, position: &PathPosition) -> Vec2 {
+        let start = match self.nodes.get(position.node) {
+            Some(&node) => Vec2::from(node),
+            None => return Vec2::ZERO,
+        };
+        match self.edge(position) {
+            Some(edge) => start.lerp(self.nodes[edge.to].into(), position.distance / edge.length),
+            None => start,
+        }
+    }
+
+    /// Unit direction of travel at `position`, if it is on an edge.
+    pub fn direction(&self, position: &PathPosition) -> Option<Vec2> {
+        self.edge(position).map(|edge| {
+            (Vec2::from(self.nodes[edge.to]) - Vec2::from(self.nodes[position.node])).normalize()
+        })
+    }
+
+    /// Shortest distance left from `position` to an end, in world units.
+    pub fn remaining(&self, position: &PathPosition) -> f32 {
+        match self.edge(position) {
+            Some(edge) => edge.length - position.distance + self.to_end[edge.to],
+            None => self.to_end.get(position.node).copied().unwrap_or(0.0),
+        }
+    }
+
+    /// Both ends of every edge, in world units.
+    pub fn segments(&self) -> impl Iterator<Item = (Vec2, Vec2)> + '_ {
+        self.edges
+            .iter()
+            .enumerate()
+            .flat_map(move |(from, edges)| {
+                edges
+                    .iter()
+                    .map(move |edge| (self.nodes[from].into(), self.nodes[edge.to].into()))
+            })
+    }
+
+    /// Points every `spacing` world units along the branch leaving `node`
+    /// through its `edge`-th edge, up to where it forks again, merges into
+    /// another branch or ends.
+    pub fn branch_points(&self, node: usize, edge: usize, spacing: f32) -> Vec<Vec2> {
+        let incoming = |node: usize| {
+            self.edges
+                .iter()
+                .flatten()
+                .filter(|edge| edge.to == node)
+                .count()
+        };
+
+        let mut points = Vec::new();
+        let (mut from, mut edge) = (node, self.edges[node][edge]);
+        let mut visited = vec![false; self.nodes.len()];
+        loop {
+            let (start, end) = (
+                Vec2::from(self.nodes[from]),
+                Vec2::from(self.nodes[edge.to]),
+            );
+            let steps = (edge.length / spacing).ceil().max(1.0) as usize;
+            points.extend((0..steps).map(|i| start.lerp(end, i as f32 / steps as f32)));
+
+            visited[from] = true;
+            let next = &self.edges[edge.to];
+            if next.len() != 1 || incoming(edge.to) != 1 || visited[edge.to] {
+                points.push(end);
+                return points;
+            }
+            from = edge.to;
+            edge = next[0];
+        }
+    }
+}
+
+/// Picks the edge to take out of a fork. `defense` rates how well defended
+/// the branch through each edge is, and `roll` is a random number in `[0, 1)`.
+pub fn choose_branch(
+    choice: BranchChoice,
+    edges: &[Edge],
+    defense: impl Fn(usize) -> f32,
+    roll: f32,
+) -> usize {
+    match choice {
+        BranchChoice::Weighted => {
+            let total: f32 = edges.iter().map(|edge| edge.weight.max(0.0)).sum();
+            let mut target = roll * total;
+            for (i, edge) in edges.iter().enumerate() {
+                target -= edge.weight.max(0.0);
+                if target < 0.0 {
+                    return i;
+                }
+            }
+            0
+        }
+        BranchChoice::LeastDefended => (0..edges.len())
+            .map(|i| (i, defense(i)))
+            .fold(
+                None,
+                |best: Option<(usize, f32)>, (i, defense)| match best {
+                    Some((_, best_defense)) if best_defense <= defense => best,
+                    _ => Some((i, defense)),
+                },
+            )
+            .map_or(0, |(i, _)| i),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::coord::CELL_SIZE;
+
+    fn coords(nodes: &[(i32, i32)]) -> Vec<Coord> {
+        nodes.iter().map(|&node| node.into()).collect()
+    }
+
+    /// A path that forks at (2, 0) into a short branch straight to (4, 0)
+    /// and a long one around through (2, 2) and (4, 2).
+    fn forked_path() -> Path {
+        let mut path = Path::new(&coords(&[(0, 0), (2, 0), (4, 0), (6, 0)]));
+        assert!(path.add_branch(&coords(&[(2, 0), (2, 2), (4, 2), (4, 0)]), 3.0));
+        path
+    }
+
+    #[test]
+    fn walks_a_straight_path() {
+        let path = Path::new(&coords(&[(0, 0), (2, 0), (2, 1)]));
+        let mut position = path.start();
+        assert_eq!(path.remaining(&position), 3.0 * CELL_SIZE);
+
+        assert!(!path.advance(&mut position, 2.5 * CELL_SIZE, |_, _| unreachable!()));
+        assert_eq!(path.sample(&position), Vec2::new(2.0, 0.5) * CELL_SIZE);
+        assert_eq!(path.direction(&position), Some(Vec2::Y));
+        assert_eq!(path.remaining(&position), 0.5 * CELL_SIZE);
+
+        assert!(path.advance(&mut position, CELL_SIZE, |_, _| unreachable!()));
+        assert_eq!(path.sample(&position), Vec2::new(2.0, 1.0) * CELL_SIZE);
+    }
+
+    #[test]
+    fn takes_the_chosen_branch_across_the_fork() {
+        let path = forked_path();
+        for (choice, expected) in [(0, Vec2::new(3.0, 0.0)), (1, Vec2::new(2.0, 1.0))] {
+            let mut position = path.start();
+            let mut forks = Vec::new();
+            // Crosses the fork within a single step.
+            path.advance(&mut position, 3.0 * CELL_SIZE, |node, edges| {
+                forks.push((node, edges.len()));
+                choice
+            });
+            assert_eq!(forks, [(1, 2)]);
+            assert_eq!(path.sample(&position), expected * CELL_SIZE);
+        }
+    }
+
+    #[test]
+    fn merges_back_after_the_long_branch() {
+        let path = forked_path();
+        let mut position = path.start();
+        assert!(!path.advance(&mut position, 8.0 * CELL_SIZE, |_, _| 1));
+        assert_eq!(path.sample(&position), Vec2::new(4.0, 0.0) * CELL_SIZE);
+        assert!(path.advance(&mut position, 2.0 * CELL_SIZE, |_, _| 1));
+    }
+
+    #[test]
+    fn remaining_distance_is_the_shortest_route() {
+        let path = forked_path();
+        assert_eq!(path.remaining(&path.start()), 6.0 * CELL_SIZE);
+
+        let mut position = path.start();
+        path.advance(&mut position, 3.0 * CELL_SIZE, |_, _| 1);
+        assert_eq!(path.remaining(&position), 7.0 * CELL_SIZE);
+    }
+
+    #[test]
+    fn branches_must_start_on_the_path() {
+        let mut path = Path::new(&coords(&[(0, 0), (2, 0)]));
+        assert!(!path.add_branch(&coords(&[(1, 1), (2, 0)]), 1.0));
+        assert_eq!(path.node_index((1, 1).into()), None);
+    }
+
+    #[test]
+    fn weighted_choice_follows_weights() {
+        let path = forked_path();
+        let edges = &path.edges[1];
+        let no_defense = |_| unreachable!();
+        assert_eq!(
+            choose_branch(BranchChoice::Weighted, edges, no_defense, 0.0),
+            0
+        );
+        assert_eq!(
+            choose_branch(BranchChoice::Weighted, edges, no_defense, 0.2),
+            0
+        );
+        assert_eq!(
+            choose_branch(BranchChoice::Weighted, edges, no_defense, 0.3),
+            1
+        );
+        assert_eq!(
+            choose_branch(BranchChoice::Weighted, edges, no_defense, 0.99),
+            1
+        );
+    }
+
+    #[test]
+    fn least_defended_choice_avoids_towers() {
+        let path = forked_path();
+        let edges = &path.edges[1];
+        let defenses = [2.0, 1.0];
+        let choose = |defenses: [f32; 2]| {
+            choose_branch(BranchChoice::LeastDefended, edges, |i| defenses[i], 0.0)
+        };
+        assert_eq!(choose(defenses), 1);
+        assert_eq!(choose([0.0, 1.0]), 0);
+        // Ties go to the first branch.
+        assert_eq!(choose([1.0, 1.0]), 0);
+    }
+
+    #[test]
+    fn branch_points_stop_where_branches_merge() {
+        let path = forked_path();
+        let long = path.branch_points(1, 1, CELL_SIZE);
+        assert_eq!(long.first(), Some(&(Vec2::new(2.0, 0.0) * CELL_SIZE)));
+        assert_eq!(long.last(), Some(&(Vec2::new(4.0, 0.0) * CELL_SIZE)));
+        assert_eq!(long.len(), 7);
+
+        let short = path.branch_points(1, 0, CELL_SIZE);
+        assert_eq!(
+            short,
+            [
+                Vec2::new(2.0, 0.0),
+                Vec2::new(3.0, 0.0),
+                Vec2::new(4.0, 0.0)
+            ]
+            .map(|p| p * CELL_SIZE)
+        );
+    }
+}
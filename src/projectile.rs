@@ -1,13 +1,12 @@
-use bevy::{prelude::*, sprite::Mesh2dHandle};
-use bevy_kira_audio::Audio;
-use iyes_loopless::prelude::*;
+use bevy::prelude::*;
 
 use crate::{
-    audio::AudioHandleMap,
+    clock::GameClock,
     enemy::Enemy,
-    game_state::GameState,
     health::Health,
-    mesh::{MeshMaterial, RegPoly},
+    map::MapEntity,
+    simulation::{SimulationAppExt, SimulationPhase},
+    tower::Tower,
 };
 
 pub struct ProjectilePlugin;
@@ -15,81 +14,80 @@ pub struct ProjectilePlugin;
 impl Plugin for ProjectilePlugin {
     fn build(&self, app: &mut App) {
         app.add_event::<SpawnProjectile>()
-            .add_startup_system(projectile_setup)
-            .add_system_set(
-                ConditionSet::new()
-                    .run_in_state(GameState::Playing)
-                    .with_system(apply_velocity)
-                    .with_system(projectile_spawn)
-                    .with_system(projectile_hit)
-                    .with_system(projectile_destroy)
-                    .into(),
-            );
+            .add_event::<EnemyHit>()
+            .add_simulation_system(SimulationPhase::Move, apply_velocity)
+            .add_simulation_system(SimulationPhase::Hit, projectile_spawn)
+            .add_simulation_system(SimulationPhase::Hit, projectile_hit)
+            .add_simulation_system(SimulationPhase::Cleanup, projectile_destroy);
     }
 }
 
 #[derive(Component)]
-struct Projectile {
+pub struct Projectile {
     creation_time: f64,
-}
-
-#[derive(Deref)]
-struct ProjectileAssets(MeshMaterial);
-
-fn projectile_setup(
-    mut commands: Commands,
-    mut materials: ResMut<Assets<ColorMaterial>>,
-    mut meshes: ResMut<Assets<Mesh>>,
-) {
-    commands.insert_resource(ProjectileAssets(MeshMaterial {
-        mesh: Mesh2dHandle(meshes.add(RegPoly::fill(8, 2.0).into())),
-        material: materials.add(Color::rgb(0.1, 0.1, 0.1).into()),
-    }));
+    damage: i32,
+    /// The tower that fired it, credited with the kill.
+    tower: Entity,
 }
 
 pub struct SpawnProjectile {
+    pub tower: Entity,
     pub position: Vec2,
     pub direction: Vec2,
+    pub speed: f32,
+    pub damage: i32,
 }
 
 fn projectile_spawn(
     mut commands: Commands,
-    time: Res<Time>,
-    assets: Res<ProjectileAssets>,
+    clock: Res<GameClock>,
     mut events: EventReader<SpawnProjectile>,
 ) {
     for event in events.iter() {
         commands
-            .spawn_bundle(ColorMesh2dBundle {
-                mesh: assets.mesh.clone(),
-                material: assets.material.clone(),
-                transform: Transform::from_translation(event.position.extend(0.0)),
-                ..Default::default()
-            })
+            .spawn_bundle(TransformBundle::from_transform(
+                Transform::from_translation(event.position.extend(0.5)),
+            ))
             .insert(Projectile {
-                creation_time: time.seconds_since_startup(),
+                creation_time: clock.elapsed(),
+                damage: event.damage,
+                tower: event.tower,
             })
-            .insert(Velocity(event.direction.normalize_or_zero() * 200.0));
+            .insert(Velocity(event.direction.normalize_or_zero() * event.speed))
+            .insert(MapEntity);
     }
 }
 
+/// How far outside of an enemy's radius a projectile still counts as a hit.
+const HIT_MARGIN: f32 = 8.0;
+
+/// Sent when a projectile hits an enemy.
+pub struct EnemyHit;
+
 fn projectile_hit(
     mut commands: Commands,
-    sounds: Res<AudioHandleMap>,
-    audio: Res<Audio>,
-    projectile_query: Query<(Entity, &Transform), With<Projectile>>,
-    mut enemy_query: Query<(&mut Health, &Transform), With<Enemy>>,
+    mut events: EventWriter<EnemyHit>,
+    projectile_query: Query<(Entity, &Projectile, &Transform)>,
+    mut enemy_query: Query<(&Enemy, &mut Health, &Transform)>,
+    mut tower_query: Query<&mut Tower>,
 ) {
-    for (projectile_entity, projectile_transform) in projectile_query.iter() {
-        for (mut enemy_health, enemy_transform) in enemy_query.iter_mut() {
+    for (projectile_entity, projectile, projectile_transform) in projectile_query.iter() {
+        for (enemy, mut enemy_health, enemy_transform) in enemy_query.iter_mut() {
             if projectile_transform
                 .translation
                 .distance(enemy_transform.translation)
-                < 20.0
+                < enemy.radius + HIT_MARGIN
             {
-                enemy_health.damage(1);
+                let was_alive = enemy_health.current > 0;
+                enemy_health.damage(projectile.damage);
+                if was_alive && enemy_health.current <= 0 {
+                    // The tower might have been sold in the meantime.
+                    if let Ok(mut tower) = tower_query.get_mut(projectile.tower) {
+                        tower.kills += 1;
+                    }
+                }
                 commands.entity(projectile_entity).despawn();
-                audio.play(sounds.enemy_hit.clone());
+                events.send(EnemyHit);
                 // Projectiles should only affect a single enemy.
                 break;
             }
@@ -99,11 +97,11 @@ fn projectile_hit(
 
 fn projectile_destroy(
     mut commands: Commands,
-    time: Res<Time>,
+    clock: Res<GameClock>,
     query: Query<(Entity, &Projectile)>,
 ) {
     for (entity, projectile) in query.iter() {
-        if time.seconds_since_startup() - projectile.creation_time > 2.0 {
+        if clock.elapsed() - projectile.creation_time > 2.0 {
             commands.entity(entity).despawn();
         }
     }
@@ -112,8 +110,8 @@ fn projectile_destroy(
 #[derive(Component)]
 struct Velocity(Vec2);
 
-fn apply_velocity(time: Res<Time>, mut query: Query<(&mut Transform, &Velocity)>) {
+fn apply_velocity(clock: Res<GameClock>, mut query: Query<(&mut Transform, &Velocity)>) {
     for (mut transform, velocity) in query.iter_mut() {
-        transform.translation += velocity.0.extend(0.0) * time.delta_seconds();
+        transform.translation += velocity.0.extend(0.0) * clock.delta_seconds();
     }
 }
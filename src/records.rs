@@ -0,0 +1,142 @@
+use bevy::{asset::FileAssetIo, prelude::*};
+use iyes_loopless::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use std::{collections::BTreeMap, fmt, fs, io, path::PathBuf};
+
+use crate::{
+    base::Base, game_state::GameState, health::Health, map::MapSource, ron_asset::parse_ron,
+    wave::CurrentWave,
+};
+
+pub struct RecordsPlugin;
+
+impl Plugin for RecordsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(records_load)
+            .add_enter_system(GameState::Victory, record_result)
+            .add_enter_system(GameState::GameOver, record_result);
+    }
+}
+
+/// Best results are kept in `records.ron` beside the assets directory rather
+/// than in it: in the crate root when run through cargo, next to the
+/// executable otherwise.
+fn records_path() -> PathBuf {
+    FileAssetIo::get_root_path().join("records.ron")
+}
+
+/// The best result on a map.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct MapRecord {
+    pub victory: bool,
+    /// The last wave reached.
+    pub wave: usize,
+    pub base_health: i32,
+}
+
+impl MapRecord {
+    /// Winning beats losing, then getting further, then keeping more health.
+    pub fn is_better_than(&self, other: &MapRecord) -> bool {
+        (self.victory, self.wave, self.base_health) > (other.victory, other.wave, other.base_health)
+    }
+}
+
+impl fmt::Display for MapRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.victory {
+            write!(f, "Won with {} health", self.base_health)
+        } else {
+            write!(f, "Reached wave {}", self.wave)
+        }
+    }
+}
+
+/// Best results by map file. Generated maps have no records.
+#[derive(Default, Deserialize, Serialize)]
+pub struct Records(pub BTreeMap<String, MapRecord>);
+
+impl Records {
+    /// Keeps `record` if it beats the current one for `map`. Returns whether it
+    /// did.
+    pub fn update(&mut self, map: &str, record: MapRecord) -> bool {
+        match self.0.get(map) {
+            Some(best) if !record.is_better_than(best) => false,
+            _ => {
+                self.0.insert(map.to_string(), record);
+                true
+            }
+        }
+    }
+
+    fn save(&self) -> anyhow::Result<()> {
+        let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::new())?;
+        fs::write(records_path(), text)?;
+        Ok(())
+    }
+}
+
+fn records_load(mut commands: Commands) {
+    let path = records_path();
+    let records = match fs::read(&path) {
+        Ok(bytes) => parse_ron(&path, &bytes).unwrap_or_else(|err| {
+            error!("Could not read records: {}", err);
+            Records::default()
+        }),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Records::default(),
+        Err(err) => {
+            error!("Could not read records: {}", err);
+            Records::default()
+        }
+    };
+    commands.insert_resource(records);
+}
+
+fn record_result(
+    mut records: ResMut<Records>,
+    source: Res<MapSource>,
+    game_state: Res<CurrentState<GameState>>,
+    current_wave: Res<CurrentWave>,
+    base_query: Query<&Health, With<Base>>,
+) {
+    let path = match &*source {
+        MapSource::File(path) => path,
+        MapSource::Generated(_) | MapSource::Asset(_) => return,
+    };
+    let record = MapRecord {
+        victory: game_state.0 == GameState::Victory,
+        wave: current_wave.number,
+        base_health: base_query
+            .get_single()
+            .map_or(0, |health| health.current.max(0)),
+    };
+    if records.update(path, record) {
+        if let Err(err) = records.save() {
+            error!("Could not save records: {}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_best_result() {
+        let record = |victory, wave, base_health| MapRecord {
+            victory,
+            wave,
+            base_health,
+        };
+        let mut records = Records::default();
+
+        assert!(records.update("a", record(false, 3, 0)));
+        assert!(!records.update("a", record(false, 2, 0)));
+        assert!(records.update("a", record(true, 6, 2)));
+        assert!(!records.update("a", record(false, 6, 10)));
+        assert!(records.update("a", record(true, 6, 5)));
+        assert!(records.update("b", record(false, 1, 0)));
+
+        assert_eq!(records.0["a"], record(true, 6, 5));
+    }
+}
@@ -0,0 +1,94 @@
+use bevy::asset::{Asset, AssetLoader, BoxedFuture, FileAssetIo, LoadContext, LoadedAsset};
+use serde::de::DeserializeOwned;
+
+use std::{error::Error, fmt, marker::PhantomData, path::PathBuf};
+
+/// Loads any deserializable asset from a RON file with one of the given
+/// extensions.
+pub struct RonAssetLoader<T> {
+    extensions: &'static [&'static str],
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> RonAssetLoader<T> {
+    pub fn new(extensions: &'static [&'static str]) -> Self {
+        Self {
+            extensions,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T: Asset + DeserializeOwned> AssetLoader for RonAssetLoader<T> {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let asset: T = parse_ron(load_context.path(), bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(asset));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        self.extensions
+    }
+}
+
+/// `path` within the assets directory, found the same way the asset server
+/// finds it: in the crate root when run through cargo, next to the executable
+/// otherwise.
+pub fn asset_path(path: impl AsRef<std::path::Path>) -> PathBuf {
+    FileAssetIo::get_root_path().join("assets").join(path)
+}
+
+/// Deserializes a RON document, keeping track of the file and the field that
+/// failed so the error can point at both.
+pub fn parse_ron<T: DeserializeOwned>(
+    path: &std::path::Path,
+    bytes: &[u8],
+) -> Result<T, RonAssetError> {
+    let error = |field: String, source: ron::Error| RonAssetError {
+        path: path.to_owned(),
+        field,
+        source,
+    };
+
+    let mut deserializer =
+        ron::Deserializer::from_bytes(bytes).map_err(|source| error(String::new(), source))?;
+    serde_path_to_error::deserialize(&mut deserializer)
+        .map_err(|err| error(err.path().to_string(), err.into_inner()))
+}
+
+#[derive(Debug)]
+pub struct RonAssetError {
+    path: PathBuf,
+    field: String,
+    source: ron::Error,
+}
+
+impl fmt::Display for RonAssetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // serde_path_to_error reports "." (or "?" for syntax errors) when the
+        // error can't be attributed to a field.
+        if matches!(self.field.as_str(), "" | "." | "?") {
+            write!(f, "{}: {}", self.path.display(), self.source)
+        } else {
+            write!(
+                f,
+                "{}: field `{}`: {}",
+                self.path.display(),
+                self.field,
+                self.source
+            )
+        }
+    }
+}
+
+impl Error for RonAssetError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.source)
+    }
+}
@@ -0,0 +1,195 @@
+use bevy::{
+    ecs::{
+        event::Events,
+        schedule::{IntoSystemDescriptor, SystemDescriptor},
+        system::Resource,
+    },
+    prelude::*,
+};
+use iyes_loopless::prelude::*;
+use rand::{rngs::StdRng, SeedableRng};
+
+use std::collections::HashMap;
+
+use crate::{
+    clock::{GameClock, TICK_SECONDS},
+    game_state::GameState,
+};
+
+pub struct SimulationPlugin;
+
+impl Plugin for SimulationPlugin {
+    fn build(&self, app: &mut App) {
+        let mut schedule = Schedule::default();
+        for phase in SimulationPhase::ALL {
+            schedule.add_stage(phase, SystemStage::single_threaded());
+        }
+
+        // State transitions are added before `Update` as well, so ticks see
+        // the state of the current frame.
+        app.init_resource::<GameClock>()
+            .init_resource::<SimulationRng>()
+            .add_stage_before(
+                CoreStage::Update,
+                SimulationStageLabel,
+                SimulationStage {
+                    schedule,
+                    system_count: 0,
+                    last_systems: HashMap::new(),
+                },
+            );
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, StageLabel)]
+struct SimulationStageLabel;
+
+/// The parts of a simulation tick, run in this order. Commands are applied
+/// at the end of each phase, so entities spawned in one phase are seen by the
+/// next.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, StageLabel)]
+pub enum SimulationPhase {
+    /// The map is filled in, and towers the player asked for are built,
+    /// upgraded or sold.
+    Input,
+    /// Enemies leave their spawners.
+    Spawn,
+    /// Enemies walk and projectiles fly.
+    Move,
+    /// Towers turn toward their targets and shoot.
+    Shoot,
+    /// Projectiles are fired and hit enemies.
+    Hit,
+    /// Destroyed things are removed and the outcome of the game is decided.
+    Cleanup,
+}
+
+impl SimulationPhase {
+    pub const ALL: [SimulationPhase; 6] = [
+        SimulationPhase::Input,
+        SimulationPhase::Spawn,
+        SimulationPhase::Move,
+        SimulationPhase::Shoot,
+        SimulationPhase::Hit,
+        SimulationPhase::Cleanup,
+    ];
+}
+
+/// Most ticks run in a single frame. When frames take longer than that, the
+/// game slows down instead of falling further and further behind.
+const MAX_TICKS_PER_FRAME: u32 = 12;
+
+/// When present, every update runs this many ticks while playing, whatever
+/// the real time and the speed. Used to run the game headless.
+pub struct TicksPerUpdate(pub u32);
+
+/// Runs the gameplay simulation in ticks of [`TICK_SECONDS`] of game time, as
+/// many as the [`GameClock`] or [`TicksPerUpdate`] ask for. Ticks only run
+/// while playing, or one at a time when stepping while paused, and stop as
+/// soon as the game state is about to change.
+struct SimulationStage {
+    schedule: Schedule,
+    system_count: usize,
+    /// The system added last to each phase, which the next one runs after.
+    last_systems: HashMap<SimulationPhase, SimulationSystem>,
+}
+
+/// Tells simulation systems apart to chain them. Stages order systems that
+/// are not chained differently from run to run.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, SystemLabel)]
+struct SimulationSystem(usize);
+
+impl Stage for SimulationStage {
+    fn run(&mut self, world: &mut World) {
+        let game_state = world.resource::<CurrentState<GameState>>().0;
+        let real_delta = world.resource::<Time>().delta_seconds_f64();
+        let ticks_per_update = world.get_resource::<TicksPerUpdate>().map(|ticks| ticks.0);
+        let ticks = {
+            let mut clock = world.resource_mut::<GameClock>();
+            match (game_state, ticks_per_update) {
+                (GameState::Playing, Some(ticks)) => ticks,
+                (GameState::Playing, None) => clock.due_ticks(real_delta).min(MAX_TICKS_PER_FRAME),
+                (GameState::Paused, _) => u32::from(clock.take_step()),
+                _ => 0,
+            }
+        };
+
+        for _ in 0..ticks {
+            if world.contains_resource::<NextState<GameState>>() {
+                break;
+            }
+            world.resource_mut::<GameClock>().advance(TICK_SECONDS);
+            self.schedule.run_once(world);
+        }
+    }
+}
+
+pub trait SimulationAppExt {
+    /// Adds a system to `phase` of every simulation tick. Systems in a phase
+    /// run in the order they are added.
+    fn add_simulation_system<Params>(
+        &mut self,
+        phase: SimulationPhase,
+        system: impl IntoSystemDescriptor<Params>,
+    ) -> &mut Self;
+
+    /// Adds an event read by simulation systems. Unlike with `add_event`,
+    /// events are kept until ticks have run rather than frames, so none are
+    /// lost on frames without a tick. Events left over from the last map are
+    /// dropped.
+    fn add_simulation_event<T: Resource>(&mut self) -> &mut Self;
+}
+
+impl SimulationAppExt for App {
+    fn add_simulation_system<Params>(
+        &mut self,
+        phase: SimulationPhase,
+        system: impl IntoSystemDescriptor<Params>,
+    ) -> &mut Self {
+        let stage = self
+            .schedule
+            .get_stage_mut::<SimulationStage>(&SimulationStageLabel)
+            .expect("SimulationPlugin must be added before gameplay plugins");
+        let system = match system.into_descriptor() {
+            SystemDescriptor::Parallel(system) => {
+                let label = SimulationSystem(stage.system_count);
+                stage.system_count += 1;
+                let system = match stage.last_systems.insert(phase, label.clone()) {
+                    Some(previous) => system.label(label).after(previous),
+                    None => system.label(label),
+                };
+                SystemDescriptor::Parallel(system)
+            }
+            // Exclusive systems run at the start of the phase anyway.
+            system => system,
+        };
+        stage.schedule.add_system_to_stage(phase, system);
+        self
+    }
+
+    fn add_simulation_event<T: Resource>(&mut self) -> &mut Self {
+        self.init_resource::<Events<T>>()
+            .add_enter_system(GameState::LoadingMap, simulation_events_clear::<T>)
+            .add_simulation_system(SimulationPhase::Cleanup, Events::<T>::update_system)
+    }
+}
+
+fn simulation_events_clear<T: Resource>(mut events: ResMut<Events<T>>) {
+    events.clear();
+}
+
+/// The only source of randomness in the simulation. It is seeded when a map
+/// is set up, so the same map and the same inputs play out the same way.
+pub struct SimulationRng(pub StdRng);
+
+impl Default for SimulationRng {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl SimulationRng {
+    pub fn new(seed: u64) -> Self {
+        Self(StdRng::seed_from_u64(seed))
+    }
+}
@@ -0,0 +1,17 @@
+use bevy::prelude::*;
+
+pub struct StatsPlugin;
+
+impl Plugin for StatsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GameStats>();
+    }
+}
+
+/// Running totals for the current attempt at a map, shown on the summary
+/// screen.
+#[derive(Default)]
+pub struct GameStats {
+    pub kills: u32,
+    pub coins_earned: i32,
+}
@@ -0,0 +1,183 @@
+use bevy::prelude::*;
+
+use std::cmp::Ordering;
+
+/// Which enemy in range a tower prefers to shoot at.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TargetPriority {
+    /// Closest to the base along its path.
+    First,
+    /// Furthest from the base along its path.
+    Last,
+    /// Most health left.
+    Strongest,
+    /// Least health left.
+    Weakest,
+    #[default]
+    Closest,
+}
+
+impl TargetPriority {
+    pub const ALL: [TargetPriority; 5] = [
+        TargetPriority::First,
+        TargetPriority::Last,
+        TargetPriority::Strongest,
+        TargetPriority::Weakest,
+        TargetPriority::Closest,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            TargetPriority::First => "First",
+            TargetPriority::Last => "Last",
+            TargetPriority::Strongest => "Strongest",
+            TargetPriority::Weakest => "Weakest",
+            TargetPriority::Closest => "Closest",
+        }
+    }
+}
+
+/// What a tower needs to know about an enemy to decide whether to target it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TargetCandidate {
+    pub entity: Entity,
+    pub position: Vec2,
+    pub velocity: Vec2,
+    /// Distance left to walk along its path before reaching the base.
+    pub remaining: f32,
+    pub health: i32,
+}
+
+/// Picks the enemy within `range` of `tower_position` that best matches
+/// `priority`.
+pub fn select_target(
+    priority: TargetPriority,
+    tower_position: Vec2,
+    range: f32,
+    candidates: impl IntoIterator<Item = TargetCandidate>,
+) -> Option<TargetCandidate> {
+    let distance_sq =
+        |candidate: &TargetCandidate| candidate.position.distance_squared(tower_position);
+    let in_range = candidates
+        .into_iter()
+        .filter(|candidate| distance_sq(candidate) <= range * range);
+
+    // Every priority is expressed as "highest score wins".
+    let score = |candidate: &TargetCandidate| match priority {
+        TargetPriority::First => -candidate.remaining,
+        TargetPriority::Last => candidate.remaining,
+        TargetPriority::Strongest => candidate.health as f32,
+        TargetPriority::Weakest => -candidate.health as f32,
+        TargetPriority::Closest => -distance_sq(candidate),
+    };
+
+    in_range.max_by(|a, b| score(a).partial_cmp(&score(b)).unwrap_or(Ordering::Equal))
+}
+
+/// Where a projectile fired from `shooter` at `speed` meets a target moving
+/// in a straight line at constant velocity, if it can catch it at all.
+pub fn intercept(shooter: Vec2, target: Vec2, target_velocity: Vec2, speed: f32) -> Option<Vec2> {
+    // Solve |offset + velocity * t| = speed * t for the smallest t > 0.
+    let offset = target - shooter;
+    let a = target_velocity.length_squared() - speed * speed;
+    let b = 2.0 * offset.dot(target_velocity);
+    let c = offset.length_squared();
+
+    let t = if a.abs() < f32::EPSILON {
+        // Target as fast as the projectile: the equation is linear.
+        if b >= 0.0 {
+            return None;
+        }
+        -c / b
+    } else {
+        let discriminant = b * b - 4.0 * a * c;
+        if discriminant < 0.0 {
+            return None;
+        }
+        let root = discriminant.sqrt();
+        let (t1, t2) = ((-b - root) / (2.0 * a), (-b + root) / (2.0 * a));
+        match (t1 > 0.0, t2 > 0.0) {
+            (true, true) => t1.min(t2),
+            (true, false) => t1,
+            (false, true) => t2,
+            (false, false) => return None,
+        }
+    };
+
+    Some(target + target_velocity * t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(id: u32, x: f32, remaining: f32, health: i32) -> TargetCandidate {
+        TargetCandidate {
+            entity: Entity::from_raw(id),
+            position: Vec2::new(x, 0.0),
+            velocity: Vec2::ZERO,
+            remaining,
+            health,
+        }
+    }
+
+    fn selected(priority: TargetPriority, candidates: &[TargetCandidate]) -> Option<u32> {
+        select_target(priority, Vec2::ZERO, 50.0, candidates.iter().copied())
+            .map(|candidate| candidate.entity.id())
+    }
+
+    #[test]
+    fn ignores_enemies_out_of_range() {
+        let candidates = [candidate(0, 60.0, 0.1, 10), candidate(1, -50.0, 0.9, 1)];
+        for priority in TargetPriority::ALL {
+            assert_eq!(selected(priority, &candidates), Some(1));
+        }
+        assert_eq!(selected(TargetPriority::First, &candidates[..1]), None);
+    }
+
+    #[test]
+    fn picks_by_priority() {
+        let candidates = [
+            candidate(0, 30.0, 0.5, 4),
+            candidate(1, -10.0, 0.8, 6),
+            candidate(2, 20.0, 0.3, 2),
+        ];
+        assert_eq!(selected(TargetPriority::First, &candidates), Some(2));
+        assert_eq!(selected(TargetPriority::Last, &candidates), Some(1));
+        assert_eq!(selected(TargetPriority::Strongest, &candidates), Some(1));
+        assert_eq!(selected(TargetPriority::Weakest, &candidates), Some(2));
+        assert_eq!(selected(TargetPriority::Closest, &candidates), Some(1));
+    }
+
+    #[test]
+    fn intercept_leads_moving_target() {
+        let shooter = Vec2::ZERO;
+        let target = Vec2::new(100.0, 0.0);
+        let velocity = Vec2::new(0.0, 50.0);
+        let point = intercept(shooter, target, velocity, 200.0).unwrap();
+
+        // The projectile and the target must arrive at the same time.
+        let projectile_time = point.distance(shooter) / 200.0;
+        let target_time = point.distance(target) / 50.0;
+        assert!((projectile_time - target_time).abs() < 1e-4);
+        assert!(point.y > 0.0);
+    }
+
+    #[test]
+    fn intercept_of_stationary_target_is_its_position() {
+        let target = Vec2::new(30.0, -40.0);
+        let point = intercept(Vec2::ZERO, target, Vec2::ZERO, 100.0).unwrap();
+        assert!(point.distance(target) < 1e-4);
+    }
+
+    #[test]
+    fn intercept_fails_for_target_outrunning_projectile() {
+        let point = intercept(
+            Vec2::ZERO,
+            Vec2::new(10.0, 0.0),
+            Vec2::new(100.0, 0.0),
+            50.0,
+        );
+        assert_eq!(point, None);
+    }
+}
@@ -0,0 +1,312 @@
+use bevy::{prelude::*, sprite::Mesh2dHandle};
+use iyes_loopless::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    camera::CursorPosition,
+    coord::{Coord, CELL_SIZE},
+    game_state::GameState,
+    map::MapEntity,
+    mesh::MeshMaterial,
+};
+
+pub struct TileMapPlugin;
+
+impl Plugin for TileMapPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(tile_map_setup).add_system(
+            terrain_spawn
+                .run_in_state(GameState::Playing)
+                .run_if_resource_exists::<TileMap>(),
+        );
+    }
+}
+
+/// What a cell of the [`TileMap`] is made of.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Terrain {
+    /// Open ground that towers can be built on.
+    Buildable,
+    /// Part of an enemy path.
+    Path,
+    /// Neither buildable nor walkable.
+    Blocked,
+    /// Walkable, but slows enemies down.
+    Water,
+    /// Buildable, and extends the range of towers built on it.
+    HighGround,
+}
+
+impl Terrain {
+    pub fn is_buildable(self) -> bool {
+        matches!(self, Terrain::Buildable | Terrain::HighGround)
+    }
+
+    /// Whether enemies finding their own way may walk over it.
+    pub fn is_walkable(self) -> bool {
+        self != Terrain::Blocked
+    }
+
+    /// Multiplier of the speed of enemies walking over it.
+    pub fn speed_factor(self) -> f32 {
+        match self {
+            Terrain::Water => 0.5,
+            _ => 1.0,
+        }
+    }
+
+    /// Range in world units added to towers built on it.
+    pub fn range_bonus(self) -> f32 {
+        match self {
+            Terrain::HighGround => CELL_SIZE,
+            _ => 0.0,
+        }
+    }
+}
+
+/// An inclusive rectangle of cells.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Bounds {
+    pub min: Coord,
+    pub max: Coord,
+}
+
+impl Bounds {
+    /// The smallest bounds containing every coordinate, if there are any.
+    pub fn around(coords: impl IntoIterator<Item = Coord>) -> Option<Bounds> {
+        coords.into_iter().fold(None, |bounds, coord| {
+            Some(match bounds {
+                Some(Bounds { min, max }) => Bounds {
+                    min: Coord::new(min.x.min(coord.x), min.y.min(coord.y)),
+                    max: Coord::new(max.x.max(coord.x), max.y.max(coord.y)),
+                },
+                None => Bounds {
+                    min: coord,
+                    max: coord,
+                },
+            })
+        })
+    }
+
+    /// These bounds, grown by `margin` cells on every side.
+    pub fn grow(self, margin: i32) -> Bounds {
+        Bounds {
+            min: Coord::new(self.min.x - margin, self.min.y - margin),
+            max: Coord::new(self.max.x + margin, self.max.y + margin),
+        }
+    }
+
+    pub fn contains(&self, coord: Coord) -> bool {
+        (self.min.x..=self.max.x).contains(&coord.x) && (self.min.y..=self.max.y).contains(&coord.y)
+    }
+
+    pub fn width(&self) -> i32 {
+        self.max.x - self.min.x + 1
+    }
+
+    pub fn height(&self) -> i32 {
+        self.max.y - self.min.y + 1
+    }
+
+    /// Every cell, row by row.
+    pub fn cells(self) -> impl Iterator<Item = Coord> {
+        (self.min.y..=self.max.y)
+            .flat_map(move |y| (self.min.x..=self.max.x).map(move |x| Coord::new(x, y)))
+    }
+}
+
+/// The terrain of every cell of the current map. Cells outside of its bounds
+/// do not exist as far as building and walking are concerned.
+pub struct TileMap {
+    bounds: Bounds,
+    /// Row by row, like [`Bounds::cells`].
+    tiles: Vec<Terrain>,
+}
+
+impl TileMap {
+    pub fn new(bounds: Bounds, fill: Terrain) -> TileMap {
+        Self {
+            bounds,
+            tiles: vec![fill; (bounds.width() * bounds.height()).max(0) as usize],
+        }
+    }
+
+    pub fn bounds(&self) -> Bounds {
+        self.bounds
+    }
+
+    fn index(&self, coord: Coord) -> Option<usize> {
+        self.bounds.contains(coord).then(|| {
+            ((coord.y - self.bounds.min.y) * self.bounds.width() + coord.x - self.bounds.min.x)
+                as usize
+        })
+    }
+
+    pub fn get(&self, coord: Coord) -> Option<Terrain> {
+        self.index(coord).map(|index| self.tiles[index])
+    }
+
+    /// Sets the terrain of `coord`, if it is within bounds.
+    pub fn set(&mut self, coord: Coord, terrain: Terrain) {
+        if let Some(index) = self.index(coord) {
+            self.tiles[index] = terrain;
+        }
+    }
+
+    pub fn fill(&mut self, area: Bounds, terrain: Terrain) {
+        for coord in area.cells() {
+            self.set(coord, terrain);
+        }
+    }
+
+    /// Marks every cell that the straight segments between `nodes` cross as
+    /// [`Terrain::Path`].
+    pub fn draw_path(&mut self, nodes: &[Coord]) {
+        for coord in path_cells(nodes) {
+            self.set(coord, Terrain::Path);
+        }
+    }
+
+    pub fn is_buildable(&self, coord: Coord) -> bool {
+        matches!(self.get(coord), Some(terrain) if terrain.is_buildable())
+    }
+
+    /// Every cell within bounds along with its terrain.
+    pub fn iter(&self) -> impl Iterator<Item = (Coord, Terrain)> + '_ {
+        self.bounds.cells().zip(self.tiles.iter().copied())
+    }
+
+    /// The cell under the cursor, if the cursor is over the map.
+    pub fn cursor_coord(&self, cursor: &CursorPosition) -> Option<Coord> {
+        let coord = cursor.coord()?;
+        self.bounds.contains(coord).then_some(coord)
+    }
+}
+
+/// Every cell that the straight segments between `nodes` cross, including the
+/// nodes themselves. Cells where segments meet come up more than once.
+pub fn path_cells(nodes: &[Coord]) -> impl Iterator<Item = Coord> + '_ {
+    let single = (nodes.len() == 1).then(|| nodes[0]);
+    let segments = nodes.windows(2).flat_map(|segment| {
+        let (from, to) = (segment[0], segment[1]);
+        let steps = (to.x - from.x).abs().max((to.y - from.y).abs()).max(1);
+        (0..=steps).map(move |step| {
+            let t = step as f32 / steps as f32;
+            Coord::new(
+                from.x + ((to.x - from.x) as f32 * t).round() as i32,
+                from.y + ((to.y - from.y) as f32 * t).round() as i32,
+            )
+        })
+    });
+    single.into_iter().chain(segments)
+}
+
+#[derive(Component)]
+struct TerrainTile;
+
+struct TerrainAssets {
+    water: MeshMaterial,
+}
+
+fn tile_map_setup(
+    mut commands: Commands,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    commands.insert_resource(TerrainAssets {
+        water: MeshMaterial {
+            mesh: Mesh2dHandle(meshes.add(shape::Quad::new(Vec2::splat(CELL_SIZE)).into())),
+            material: materials.add(Color::rgb(0.15, 0.3, 0.55).into()),
+        },
+    });
+}
+
+/// Draws the terrain that has no entity of its own whenever the tile map is
+/// replaced. Buildable cells are drawn as build spots.
+fn terrain_spawn(
+    mut commands: Commands,
+    assets: Res<TerrainAssets>,
+    tile_map: Res<TileMap>,
+    query: Query<Entity, With<TerrainTile>>,
+) {
+    if !tile_map.is_changed() {
+        return;
+    }
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    for (coord, terrain) in tile_map.iter() {
+        if terrain == Terrain::Water {
+            commands
+                .spawn_bundle(ColorMesh2dBundle {
+                    mesh: assets.water.mesh.clone(),
+                    material: assets.water.material.clone(),
+                    transform: Transform::from_translation(Vec2::from(coord).extend(0.0)),
+                    ..Default::default()
+                })
+                .insert(TerrainTile)
+                .insert(MapEntity);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cells_outside_of_bounds_do_not_exist() {
+        let bounds = Bounds {
+            min: Coord::new(-1, -2),
+            max: Coord::new(2, 1),
+        };
+        let mut tile_map = TileMap::new(bounds, Terrain::Blocked);
+        tile_map.set(Coord::new(2, -2), Terrain::HighGround);
+        tile_map.set(Coord::new(3, 0), Terrain::Buildable);
+
+        assert_eq!(tile_map.get(Coord::new(2, -2)), Some(Terrain::HighGround));
+        assert_eq!(tile_map.get(Coord::new(-1, 1)), Some(Terrain::Blocked));
+        assert_eq!(tile_map.get(Coord::new(3, 0)), None);
+        assert_eq!(tile_map.iter().count(), 16);
+    }
+
+    #[test]
+    fn paths_cover_every_cell_they_cross() {
+        let bounds = Bounds {
+            min: Coord::new(0, 0),
+            max: Coord::new(3, 3),
+        };
+        let mut tile_map = TileMap::new(bounds, Terrain::Buildable);
+        tile_map.draw_path(&[Coord::new(0, 0), Coord::new(0, 2), Coord::new(3, 2)]);
+
+        let path_cells: Vec<Coord> = tile_map
+            .iter()
+            .filter(|&(_, terrain)| terrain == Terrain::Path)
+            .map(|(coord, _)| coord)
+            .collect();
+        assert_eq!(
+            path_cells,
+            [(0, 0), (0, 1), (0, 2), (1, 2), (2, 2), (3, 2)].map(Coord::from)
+        );
+    }
+
+    #[test]
+    fn single_node_paths_cover_their_cell() {
+        let bounds = Bounds {
+            min: Coord::new(0, 0),
+            max: Coord::new(2, 2),
+        };
+        let mut tile_map = TileMap::new(bounds, Terrain::Buildable);
+        tile_map.draw_path(&[Coord::new(1, 1)]);
+
+        assert_eq!(tile_map.get(Coord::new(1, 1)), Some(Terrain::Path));
+        assert!(!tile_map.is_buildable(Coord::new(1, 1)));
+        assert_eq!(
+            tile_map
+                .iter()
+                .filter(|&(_, terrain)| terrain == Terrain::Path)
+                .count(),
+            1
+        );
+    }
+}
@@ -1,204 +1,227 @@
-use bevy::{
-    input::{mouse::MouseButtonInput, ElementState},
-    prelude::*,
-    sprite::Mesh2dHandle,
-};
-use bevy_kira_audio::Audio;
-use iyes_loopless::prelude::*;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
 
-use std::f32::consts::{PI, TAU};
+use std::{
+    collections::HashSet,
+    f32::consts::{PI, TAU},
+};
 
 use crate::{
-    audio::AudioHandleMap,
-    coord::{Coord, CELL_SIZE, HALF_CELL_SIZE},
+    clock::GameClock,
+    coord::Coord,
     currency::Currency,
-    enemy::Enemy,
-    game_state::GameState,
-    mesh::{MeshMaterial, RegPoly},
+    enemy::{Enemy, PathFollow},
+    field::{blocked_cells, OpenField},
+    health::Health,
+    map::MapEntity,
     projectile::SpawnProjectile,
+    simulation::{SimulationAppExt, SimulationPhase},
+    targeting::{intercept, select_target, TargetCandidate, TargetPriority},
+    tile_map::TileMap,
+    tower_kind::{Aim, TowerKind, TowerKinds, TowerStats, UpgradeLevel},
 };
 
 pub struct TowerPlugin;
 
 impl Plugin for TowerPlugin {
     fn build(&self, app: &mut App) {
-        app.add_event::<SpawnTower>()
-            .add_event::<SpawnBuildSpot>()
-            .init_resource::<Option<Selection>>()
-            .add_startup_system(tower_setup)
-            .add_system_set(
-                ConditionSet::new()
-                    .run_in_state(GameState::Playing)
-                    .with_system(tower_place)
-                    .with_system(tower_spawn)
-                    .with_system(tower_shoot)
-                    .with_system(build_spot_spawn)
-                    .with_system(selected_tower_radius)
-                    .into(),
-            );
+        app.add_simulation_event::<SpawnTower>()
+            .add_simulation_event::<SpawnBuildSpot>()
+            .add_simulation_event::<UpgradeTower>()
+            .add_simulation_event::<SellTower>()
+            .add_event::<TowerShot>()
+            .add_event::<TowerChanged>()
+            .init_resource::<SellRefund>()
+            .add_simulation_system(SimulationPhase::Input, build_spot_spawn)
+            .add_simulation_system(SimulationPhase::Input, tower_spawn)
+            .add_simulation_system(SimulationPhase::Input, tower_upgrade)
+            .add_simulation_system(SimulationPhase::Input, tower_sell)
+            .add_simulation_system(SimulationPhase::Shoot, tower_shoot);
     }
 }
 
-#[derive(Component, Default)]
-struct Tower {
-    target: Option<Entity>,
+#[derive(Component)]
+pub struct Tower {
+    /// Index into [`TowerKinds`].
+    pub kind: usize,
+    /// Coins spent on the tower, including upgrades.
+    pub invested: i32,
+    pub priority: TargetPriority,
+    /// Enemies destroyed by the tower's projectiles.
+    pub kills: u32,
     last_projectile_time: f64,
 }
 
-#[derive(Component, Deref)]
-pub struct GridPosition(Coord);
+impl Tower {
+    pub fn sell_value(&self, refund: SellRefund) -> i32 {
+        (self.invested as f32 * refund.0).floor() as i32
+    }
+}
+
+/// Fraction of the coins invested in a tower that is paid back when selling
+/// it.
+#[derive(Clone, Copy, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct SellRefund(pub f32);
 
-struct TowerAssets {
-    base: MeshMaterial,
-    barrel: MeshMaterial,
-    barrel_cap: MeshMaterial,
+impl Default for SellRefund {
+    fn default() -> Self {
+        Self(0.75)
+    }
 }
 
-struct SelectionAssets {
-    fill: MeshMaterial,
-    outline: MeshMaterial,
+#[derive(Component, Default)]
+pub struct TowerUpgrades {
+    /// The branch the tower is locked into, once it has been upgraded.
+    pub branch: Option<usize>,
+    /// Number of levels bought along `branch`.
+    pub level: usize,
 }
 
-fn tower_setup(
-    mut commands: Commands,
-    // asset_server: Res<AssetServer>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
-    mut meshes: ResMut<Assets<Mesh>>,
-) {
-    commands.insert_resource(TowerAssets {
-        base: MeshMaterial {
-            mesh: Mesh2dHandle(meshes.add(RegPoly::fill(6, 12.0).into())),
-            material: materials.add(Color::rgb(0.0, 0.5, 1.0).into()),
-        },
-        barrel: MeshMaterial {
-            mesh: Mesh2dHandle(meshes.add(shape::Quad::new(Vec2::new(24.0, 4.0)).into())),
-            material: materials.add(Color::rgb(0.4, 0.4, 0.4).into()),
-        },
-        barrel_cap: MeshMaterial {
-            mesh: Mesh2dHandle(meshes.add(shape::Quad::new(Vec2::new(8.0, 8.0)).into())),
-            material: materials.add(Color::rgb(0.4, 0.4, 0.4).into()),
-        },
-    });
-
-    commands.insert_resource(BuildSpotAssets(MeshMaterial {
-        mesh: Mesh2dHandle(meshes.add(shape::Quad::new(Vec2::new(30.0, 30.0)).into())),
-        material: materials.add(Color::rgb(0.3, 0.3, 0.3).into()),
-    }));
-
-    commands.insert_resource(SelectionAssets {
-        fill: MeshMaterial {
-            mesh: Mesh2dHandle(meshes.add(RegPoly::fill(40, MAX_DISTANCE).into())),
-            material: materials.add(Color::rgba(0.0, 0.5, 1.0, 0.1).into()),
-        },
-        outline: MeshMaterial {
-            mesh: Mesh2dHandle(meshes.add(RegPoly::outline(40, MAX_DISTANCE).into())),
-            material: materials.add(Color::rgb(0.0, 0.5, 1.0).into()),
-        },
-    });
+impl TowerUpgrades {
+    /// The next level that can be bought along `branch`, if any.
+    pub fn next<'a>(&self, kind: &'a TowerKind, branch: usize) -> Option<&'a UpgradeLevel> {
+        if matches!(self.branch, Some(current) if current != branch) {
+            return None;
+        }
+        kind.upgrades.get(branch)?.levels.get(self.level)
+    }
 }
 
-struct SpawnTower {
-    position: Coord,
+#[derive(Component, Deref)]
+pub struct GridPosition(pub Coord);
+
+/// Builds a tower of `kind` on `position`, if it can be afforded and the cell
+/// is free. On open fields, the tower must also leave enemies a way to the
+/// base.
+pub struct SpawnTower {
+    pub position: Coord,
+    /// Index into [`TowerKinds`].
+    pub kind: usize,
+}
+
+/// Sent when a tower is built, upgraded or sold.
+pub enum TowerChanged {
+    Built,
+    Upgraded,
+    Sold,
 }
 
 fn tower_spawn(
     mut commands: Commands,
-    assets: Res<TowerAssets>,
+    mut currency: ResMut<Currency>,
     mut events: EventReader<SpawnTower>,
+    mut changed_events: EventWriter<TowerChanged>,
+    tower_kinds: Res<TowerKinds>,
+    tile_map: Res<TileMap>,
+    field: Option<Res<OpenField>>,
+    tower_query: Query<&GridPosition, With<Tower>>,
+    enemy_query: Query<&PathFollow>,
 ) {
+    // Towers built earlier in this loop are not in the query yet.
+    let mut occupied = blocked_cells(tower_query.iter());
     for event in events.iter() {
+        let kind = match tower_kinds.get(event.kind) {
+            Some(kind) => kind,
+            None => continue,
+        };
+        if currency.coins < kind.cost
+            || !tile_map.is_buildable(event.position)
+            || occupied.contains(&event.position)
+        {
+            continue;
+        }
+        if let Some(field) = field.as_deref() {
+            let mut blocked = occupied.clone();
+            blocked.insert(event.position);
+            if !leaves_a_way(field, &tile_map, &blocked, &enemy_query) {
+                continue;
+            }
+        }
+
+        occupied.insert(event.position);
+        currency.coins -= kind.cost;
         let position: Vec2 = event.position.into();
+        let mut stats = kind.stats.clone();
+        if let Some(terrain) = tile_map.get(event.position) {
+            stats.range += terrain.range_bonus();
+        }
         commands
-            .spawn_bundle(ColorMesh2dBundle {
-                mesh: assets.base.mesh.clone(),
-                material: assets.base.material.clone(),
-                transform: Transform::from_translation(position.extend(1.0)),
-                ..Default::default()
+            .spawn_bundle(TransformBundle::from_transform(
+                Transform::from_translation(position.extend(1.0)),
+            ))
+            .insert(Tower {
+                kind: event.kind,
+                invested: kind.cost,
+                priority: TargetPriority::default(),
+                kills: 0,
+                last_projectile_time: 0.0,
             })
-            .insert(Tower::default())
+            .insert(stats)
+            .insert(TowerUpgrades::default())
             .insert(GridPosition(event.position))
-            .with_children(|parent| {
-                parent.spawn_bundle(ColorMesh2dBundle {
-                    mesh: assets.barrel.mesh.clone(),
-                    material: assets.barrel.material.clone(),
-                    transform: Transform::from_xyz(12.0, 0.0, 2.0),
-                    ..Default::default()
-                });
-                parent.spawn_bundle(ColorMesh2dBundle {
-                    mesh: assets.barrel_cap.mesh.clone(),
-                    material: assets.barrel_cap.material.clone(),
-                    transform: Transform::from_xyz(0.0, 0.0, 2.0),
-                    ..Default::default()
-                });
-            });
+            .insert(MapEntity);
+        changed_events.send(TowerChanged::Built);
     }
 }
 
+/// Whether every entrance and every walking enemy can still reach the base of
+/// an open field with towers on `blocked`.
+pub fn leaves_a_way(
+    field: &OpenField,
+    tile_map: &TileMap,
+    blocked: &HashSet<Coord>,
+    enemy_query: &Query<&PathFollow>,
+) -> bool {
+    field
+        .entrances
+        .iter()
+        .all(|&entrance| field.route(tile_map, entrance, blocked).is_some())
+        && enemy_query.iter().all(|path_follow| {
+            field
+                .reroute(tile_map, &path_follow.path, &path_follow.position, blocked)
+                .is_some()
+        })
+}
+
 const CLOCKWISE: f32 = -1.0;
 const COUNTER_CLOCKWISE: f32 = 1.0;
-const ANGULAR_SPEED: f32 = TAU / 200.0;
-const MAX_DISTANCE: f32 = 64.0;
+
+/// Sent when a tower fires a projectile.
+pub struct TowerShot;
 
 fn tower_shoot(
-    time: Res<Time>,
-    audio: Res<Audio>,
-    sounds: Res<AudioHandleMap>,
+    clock: Res<GameClock>,
     mut events: EventWriter<SpawnProjectile>,
-    mut tower_query: Query<(&mut Tower, &mut Transform), Without<Enemy>>,
-    enemy_query: Query<(Entity, &Transform), With<Enemy>>,
+    mut shot_events: EventWriter<TowerShot>,
+    mut tower_query: Query<(Entity, &mut Tower, &TowerStats, &mut Transform), Without<Enemy>>,
+    enemy_query: Query<(Entity, &Transform, &PathFollow, &Health), With<Enemy>>,
 ) {
-    for (mut tower, mut tower_transform) in tower_query.iter_mut() {
-        // Loop hack: the loop here is only to early-return from the block, not
-        // to actually loop.
-        let target_direction = loop {
-            // Check whether the current target is still in range.
-            if let Some(target) = tower.target {
-                let enemy = enemy_query.get(target);
-                if let Ok((_, enemy_transform)) = enemy {
-                    let dist_sq = tower_transform
-                        .translation
-                        .distance_squared(enemy_transform.translation);
-
-                    if dist_sq <= MAX_DISTANCE * MAX_DISTANCE {
-                        break Some(
-                            (enemy_transform.translation - tower_transform.translation).truncate(),
-                        );
-                    }
-                }
-            };
-
-            // Search for a new target.
-            let closest_enemy_direction: Option<Vec2> =
-                enemy_query
-                    .iter()
-                    .fold(None, |closest, (enemy, enemy_transform)| {
-                        let dist_sq = tower_transform
-                            .translation
-                            .distance_squared(enemy_transform.translation);
-
-                        // Skip enemy if it's out of range.
-                        if dist_sq > MAX_DISTANCE * MAX_DISTANCE {
-                            return closest;
-                        }
-
-                        // Skip enemy if it's not closer than the current closest enemy.
-                        if let Some(direction) = closest {
-                            if dist_sq >= direction.length_squared() {
-                                return closest;
-                            }
-                        }
-
-                        tower.target = Some(enemy);
-                        return Some(
-                            (enemy_transform.translation - tower_transform.translation).truncate(),
-                        );
-                    });
-
-            if closest_enemy_direction.is_none() {
-                tower.target = None;
-            }
-            break closest_enemy_direction;
-        };
+    for (tower_entity, mut tower, stats, mut tower_transform) in tower_query.iter_mut() {
+        let tower_position = tower_transform.translation.truncate();
+        let candidates = enemy_query
+            .iter()
+            .map(|(entity, transform, path_follow, health)| TargetCandidate {
+                entity,
+                position: transform.translation.truncate(),
+                velocity: path_follow.velocity,
+                remaining: path_follow.remaining(),
+                health: health.current,
+            });
+        let target_direction =
+            select_target(tower.priority, tower_position, stats.range, candidates).map(|target| {
+                let aim_point = match stats.aim {
+                    Aim::Direct => target.position,
+                    Aim::Lead => intercept(
+                        tower_position,
+                        target.position,
+                        target.velocity,
+                        stats.projectile_speed,
+                    )
+                    .unwrap_or(target.position),
+                };
+                aim_point - tower_position
+            });
 
         if let Some(target_direction) = target_direction {
             let target_angle = target_direction.into_angle();
@@ -207,8 +230,9 @@ fn tower_shoot(
                 normalize_angle(angle * axis.z)
             };
             let angle_to_target = target_angle - current_angle;
+            let angular_step = stats.turn_rate * clock.delta_seconds();
 
-            if angle_to_target.abs() > ANGULAR_SPEED {
+            if angle_to_target.abs() > angular_step {
                 let spin = if target_angle > current_angle {
                     if angle_to_target < PI {
                         COUNTER_CLOCKWISE
@@ -222,22 +246,79 @@ fn tower_shoot(
                         COUNTER_CLOCKWISE
                     }
                 };
-                tower_transform.rotate(Quat::from_rotation_z(ANGULAR_SPEED * spin));
+                tower_transform.rotate(Quat::from_rotation_z(angular_step * spin));
                 continue;
             }
             tower_transform.rotation = Quat::from_rotation_z(target_angle);
 
-            if !(tower.last_projectile_time + 1.0 < time.seconds_since_startup()) {
+            let cooldown = 1.0 / stats.fire_rate as f64;
+            if !(tower.last_projectile_time + cooldown < clock.elapsed()) {
                 continue;
             }
 
             events.send(SpawnProjectile {
+                tower: tower_entity,
                 position: tower_transform.translation.truncate(),
                 direction: target_direction,
+                speed: stats.projectile_speed,
+                damage: stats.damage,
             });
-            audio.play(sounds.tower_shoot.clone());
+            shot_events.send(TowerShot);
+
+            tower.last_projectile_time = clock.elapsed();
+        }
+    }
+}
+
+pub struct UpgradeTower {
+    pub tower: Entity,
+    pub branch: usize,
+}
+
+fn tower_upgrade(
+    mut currency: ResMut<Currency>,
+    mut events: EventReader<UpgradeTower>,
+    mut changed_events: EventWriter<TowerChanged>,
+    tower_kinds: Res<TowerKinds>,
+    mut tower_query: Query<(&mut Tower, &mut TowerStats, &mut TowerUpgrades)>,
+) {
+    for event in events.iter() {
+        let (mut tower, mut stats, mut upgrades) = match tower_query.get_mut(event.tower) {
+            Ok(tower) => tower,
+            Err(_) => continue,
+        };
+        let kind = &tower_kinds[tower.kind];
+        let upgrade = match upgrades.next(kind, event.branch) {
+            Some(upgrade) if currency.coins >= upgrade.cost => upgrade,
+            _ => continue,
+        };
+
+        currency.coins -= upgrade.cost;
+        tower.invested += upgrade.cost;
+        stats.apply(&upgrade.stats);
+        upgrades.branch = Some(event.branch);
+        upgrades.level += 1;
+        changed_events.send(TowerChanged::Upgraded);
+    }
+}
+
+pub struct SellTower {
+    pub tower: Entity,
+}
 
-            tower.last_projectile_time = time.seconds_since_startup();
+fn tower_sell(
+    mut commands: Commands,
+    mut currency: ResMut<Currency>,
+    mut events: EventReader<SellTower>,
+    mut changed_events: EventWriter<TowerChanged>,
+    refund: Res<SellRefund>,
+    tower_query: Query<&Tower>,
+) {
+    for event in events.iter() {
+        if let Ok(tower) = tower_query.get(event.tower) {
+            currency.coins += tower.sell_value(*refund);
+            commands.entity(event.tower).despawn_recursive();
+            changed_events.send(TowerChanged::Sold);
         }
     }
 }
@@ -268,128 +349,21 @@ impl IntoAngle for Vec2 {
 }
 
 #[derive(Component)]
-struct BuildSpot;
-
-#[derive(Deref)]
-struct BuildSpotAssets(MeshMaterial);
+pub struct BuildSpot;
 
 pub struct SpawnBuildSpot {
     pub position: Coord,
 }
 
-fn build_spot_spawn(
-    mut commands: Commands,
-    mut events: EventReader<SpawnBuildSpot>,
-    assets: Res<BuildSpotAssets>,
-) {
+fn build_spot_spawn(mut commands: Commands, mut events: EventReader<SpawnBuildSpot>) {
     for event in events.iter() {
         let v: Vec2 = event.position.into();
         commands
-            .spawn_bundle(ColorMesh2dBundle {
-                mesh: assets.mesh.clone(),
-                material: assets.material.clone(),
-                transform: Transform::from_translation(v.extend(0.0)),
-                ..Default::default()
-            })
+            .spawn_bundle(TransformBundle::from_transform(
+                Transform::from_translation(v.extend(0.0)),
+            ))
             .insert(BuildSpot)
-            .insert(GridPosition(event.position));
-    }
-}
-
-struct Selection(Entity);
-
-fn tower_place(
-    mut currency: ResMut<Currency>,
-    mut tower_spawn_events: EventWriter<SpawnTower>,
-    mut mouse_events: EventReader<MouseButtonInput>,
-    mut selection: ResMut<Option<Selection>>,
-    windows: Res<Windows>,
-    build_spot_query: Query<&GridPosition, With<BuildSpot>>,
-    tower_query: Query<(Entity, &GridPosition), With<Tower>>,
-    audio: Res<Audio>,
-    sounds: Res<AudioHandleMap>,
-) {
-    let window = windows.get_primary().expect("No primary window");
-    for mouse_event in mouse_events.iter() {
-        if let Some(position) = cursor_coord(&window) {
-            if mouse_event.button == MouseButton::Left && mouse_event.state == ElementState::Pressed
-            {
-                // Attempt to build a tower
-                if currency.coins >= 5
-                    && build_spot_query
-                        .iter()
-                        .any(|build_spot_position| build_spot_position.0 == position)
-                    && !tower_query
-                        .iter()
-                        .any(|(_tower, tower_position)| tower_position.0 == position)
-                {
-                    currency.coins -= 5;
-                    tower_spawn_events.send(SpawnTower { position });
-                    audio.play(sounds.tower_place.clone());
-                }
-
-                let clicked_tower = tower_query
-                    .iter()
-                    .find(|(_tower, tower_position)| tower_position.0 == position);
-
-                if let Some((tower, _tower_position)) = clicked_tower {
-                    *selection = Some(Selection(tower));
-                } else {
-                    *selection = None;
-                }
-            }
-        }
-    }
-}
-
-#[derive(Component)]
-struct SelectionRadius;
-
-fn selected_tower_radius(
-    mut commands: Commands,
-    assets: Res<SelectionAssets>,
-    selection: Res<Option<Selection>>,
-    tower_query: Query<(Entity, &Transform), With<Tower>>,
-    selection_radius_query: Query<Entity, With<SelectionRadius>>,
-) {
-    if selection.is_changed() {
-        for selection_radius in selection_radius_query.iter() {
-            commands.entity(selection_radius).despawn_recursive();
-        }
-
-        if let Some(selection) = &*selection {
-            if let Some((_, tower_transform)) =
-                tower_query.iter().find(|&(tower, _)| tower == selection.0)
-            {
-                commands
-                    .spawn_bundle(ColorMesh2dBundle {
-                        mesh: assets.fill.mesh.clone(),
-                        material: assets.fill.material.clone(),
-                        transform: tower_transform.clone(),
-                        ..Default::default()
-                    })
-                    .insert(SelectionRadius)
-                    .with_children(|parent| {
-                        parent.spawn_bundle(ColorMesh2dBundle {
-                            mesh: assets.outline.mesh.clone(),
-                            material: assets.outline.material.clone(),
-                            ..Default::default()
-                        });
-                    });
-            }
-        }
-    }
-}
-
-fn cursor_coord(window: &Window) -> Option<Coord> {
-    if let Some(position) = window.cursor_position() {
-        let half_width = window.width() * 0.5;
-        let half_height = window.height() * 0.5;
-        Some(Coord::new(
-            ((position.x as f32 - half_width + HALF_CELL_SIZE) / CELL_SIZE).floor() as i32,
-            ((position.y as f32 - half_height + HALF_CELL_SIZE) / CELL_SIZE).floor() as i32,
-        ))
-    } else {
-        None
+            .insert(GridPosition(event.position))
+            .insert(MapEntity);
     }
 }
@@ -0,0 +1,181 @@
+use bevy::{
+    input::{mouse::MouseButtonInput, ElementState},
+    prelude::*,
+    sprite::Mesh2dHandle,
+};
+use iyes_loopless::prelude::*;
+
+use crate::{
+    camera::CursorPosition,
+    game_state::GameState,
+    map::MapEntity,
+    mesh::{MeshMaterial, RegPoly},
+    tile_map::TileMap,
+    tower::{GridPosition, SpawnTower, Tower},
+    tower_kind::{SelectedTowerKind, TowerKinds, TowerStats},
+};
+
+pub struct TowerInputPlugin;
+
+impl Plugin for TowerInputPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Option<Selection>>()
+            .add_startup_system(tower_input_setup)
+            .add_enter_system(GameState::LoadingMap, selection_clear)
+            .add_system(tower_kind_select)
+            .add_system_set(
+                ConditionSet::new()
+                    .run_in_state(GameState::Playing)
+                    .with_system(tower_place)
+                    .with_system(selection_validate)
+                    .with_system(selected_tower_radius)
+                    .into(),
+            );
+    }
+}
+
+pub struct Selection(pub Entity);
+
+struct SelectionAssets {
+    fill: MeshMaterial,
+    outline: MeshMaterial,
+}
+
+fn tower_input_setup(
+    mut commands: Commands,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    // Unit circles, scaled up to the selected tower's range.
+    commands.insert_resource(SelectionAssets {
+        fill: MeshMaterial {
+            mesh: Mesh2dHandle(meshes.add(RegPoly::fill(40, 1.0).into())),
+            material: materials.add(Color::rgba(0.0, 0.5, 1.0, 0.1).into()),
+        },
+        outline: MeshMaterial {
+            mesh: Mesh2dHandle(meshes.add(RegPoly::outline(40, 1.0).into())),
+            material: materials.add(Color::rgb(0.0, 0.5, 1.0).into()),
+        },
+    });
+}
+
+const KIND_KEYS: [KeyCode; 9] = [
+    KeyCode::Key1,
+    KeyCode::Key2,
+    KeyCode::Key3,
+    KeyCode::Key4,
+    KeyCode::Key5,
+    KeyCode::Key6,
+    KeyCode::Key7,
+    KeyCode::Key8,
+    KeyCode::Key9,
+];
+
+fn tower_kind_select(
+    keys: Res<Input<KeyCode>>,
+    tower_kinds: Option<Res<TowerKinds>>,
+    mut selected: ResMut<SelectedTowerKind>,
+) {
+    let kind_count = tower_kinds.map_or(0, |tower_kinds| tower_kinds.len());
+    for (i, &key) in KIND_KEYS.iter().enumerate().take(kind_count) {
+        if keys.just_pressed(key) {
+            selected.0 = i;
+        }
+    }
+}
+
+fn selection_clear(mut selection: ResMut<Option<Selection>>) {
+    *selection = None;
+}
+
+/// Clicking a cell tries to build the selected tower kind there, and selects
+/// the tower on it, if any.
+fn tower_place(
+    selected_kind: Res<SelectedTowerKind>,
+    mut tower_spawn_events: EventWriter<SpawnTower>,
+    mut mouse_events: EventReader<MouseButtonInput>,
+    mut selection: ResMut<Option<Selection>>,
+    cursor: Res<CursorPosition>,
+    tile_map: Res<TileMap>,
+    tower_query: Query<(Entity, &GridPosition), With<Tower>>,
+) {
+    for mouse_event in mouse_events.iter() {
+        if let Some(position) = tile_map.cursor_coord(&cursor) {
+            if mouse_event.button == MouseButton::Left && mouse_event.state == ElementState::Pressed
+            {
+                tower_spawn_events.send(SpawnTower {
+                    position,
+                    kind: selected_kind.0,
+                });
+
+                let clicked_tower = tower_query
+                    .iter()
+                    .find(|(_tower, tower_position)| tower_position.0 == position);
+
+                if let Some((tower, _tower_position)) = clicked_tower {
+                    *selection = Some(Selection(tower));
+                } else {
+                    *selection = None;
+                }
+            }
+        }
+    }
+}
+
+/// Drops the selection once the selected tower is sold.
+fn selection_validate(
+    mut selection: ResMut<Option<Selection>>,
+    tower_query: Query<(), With<Tower>>,
+) {
+    if matches!(&*selection, Some(selected) if tower_query.get(selected.0).is_err()) {
+        *selection = None;
+    }
+}
+
+#[derive(Component)]
+struct SelectionRadius;
+
+fn selected_tower_radius(
+    mut commands: Commands,
+    assets: Res<SelectionAssets>,
+    selection: Res<Option<Selection>>,
+    tower_query: Query<(Entity, &Transform, &TowerStats), With<Tower>>,
+    changed_query: Query<(), (With<Tower>, Changed<TowerStats>)>,
+    selection_radius_query: Query<Entity, With<SelectionRadius>>,
+) {
+    // Upgrades might change the range of the selected tower.
+    let stats_changed =
+        matches!(&*selection, Some(selected) if changed_query.get(selected.0).is_ok());
+    if selection.is_changed() || stats_changed {
+        for selection_radius in selection_radius_query.iter() {
+            commands.entity(selection_radius).despawn_recursive();
+        }
+
+        if let Some(selection) = &*selection {
+            if let Some((_, tower_transform, stats)) = tower_query
+                .iter()
+                .find(|&(tower, _, _)| tower == selection.0)
+            {
+                commands
+                    .spawn_bundle(ColorMesh2dBundle {
+                        mesh: assets.fill.mesh.clone(),
+                        material: assets.fill.material.clone(),
+                        transform: Transform {
+                            scale: Vec3::splat(stats.range),
+                            ..*tower_transform
+                        },
+                        ..Default::default()
+                    })
+                    .insert(SelectionRadius)
+                    .insert(MapEntity)
+                    .with_children(|parent| {
+                        parent.spawn_bundle(ColorMesh2dBundle {
+                            mesh: assets.outline.mesh.clone(),
+                            material: assets.outline.material.clone(),
+                            ..Default::default()
+                        });
+                    });
+            }
+        }
+    }
+}
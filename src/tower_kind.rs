@@ -0,0 +1,157 @@
+use bevy::{prelude::*, reflect::TypeUuid};
+use serde::Deserialize;
+
+use crate::ron_asset::RonAssetLoader;
+
+pub struct TowerKindPlugin;
+
+impl Plugin for TowerKindPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<TowerKindList>()
+            .add_asset_loader(RonAssetLoader::<TowerKindList>::new(&["towers.ron"]))
+            .init_resource::<SelectedTowerKind>()
+            .add_startup_system(tower_kind_setup)
+            .add_system(tower_kinds_update);
+    }
+}
+
+/// Cost, stats and looks of a buildable tower.
+#[derive(Clone, Deserialize)]
+pub struct TowerKind {
+    pub name: String,
+    pub cost: i32,
+    pub stats: TowerStats,
+    pub visuals: TowerVisuals,
+    /// Mutually exclusive upgrade branches. Buying the first level of a branch
+    /// locks the tower into it.
+    #[serde(default)]
+    pub upgrades: Vec<UpgradeBranch>,
+}
+
+#[derive(Component, Clone, Deserialize)]
+pub struct TowerStats {
+    /// Targeting range in world units.
+    pub range: f32,
+    /// Shots per second.
+    pub fire_rate: f32,
+    pub damage: i32,
+    /// Projectile speed in world units per second.
+    pub projectile_speed: f32,
+    /// Turret rotation speed in radians per second.
+    pub turn_rate: f32,
+    #[serde(default)]
+    pub aim: Aim,
+}
+
+#[derive(Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+pub enum Aim {
+    /// Shoot at where the target is now.
+    #[default]
+    Direct,
+    /// Shoot at where the target will be when the projectile gets there.
+    Lead,
+}
+
+impl TowerStats {
+    pub fn apply(&mut self, changes: &StatChanges) {
+        self.range += changes.range;
+        self.fire_rate += changes.fire_rate;
+        self.damage += changes.damage;
+        self.projectile_speed += changes.projectile_speed;
+        self.turn_rate += changes.turn_rate;
+    }
+}
+
+/// Amounts added to a tower's stats. Omitted fields are left unchanged.
+#[derive(Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct StatChanges {
+    pub range: f32,
+    pub fire_rate: f32,
+    pub damage: i32,
+    pub projectile_speed: f32,
+    pub turn_rate: f32,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct UpgradeBranch {
+    pub name: String,
+    /// Color of the tower once it has been upgraded along this branch.
+    pub color: [f32; 3],
+    pub levels: Vec<UpgradeLevel>,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct UpgradeLevel {
+    pub cost: i32,
+    pub stats: StatChanges,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct TowerVisuals {
+    pub sides: u32,
+    pub radius: f32,
+    pub color: [f32; 3],
+    pub barrel_length: f32,
+    pub barrel_width: f32,
+    pub barrel_color: [f32; 3],
+}
+
+/// The contents of `assets/data/default.towers.ron`.
+#[derive(Deserialize, TypeUuid)]
+#[serde(transparent)]
+#[uuid = "8584562c-b0e3-4de2-a817-2b0fa23b0229"]
+pub struct TowerKindList {
+    kinds: Vec<TowerKind>,
+}
+
+/// Every buildable tower kind, indexed by position in the tower kind file.
+/// Only present once the file has been loaded.
+#[derive(Deref)]
+pub struct TowerKinds(Vec<TowerKind>);
+
+impl TowerKinds {
+    pub fn index_of(&self, name: &str) -> Option<usize> {
+        self.iter().position(|kind| kind.name == name)
+    }
+}
+
+pub fn tower_kinds_loaded(tower_kinds: Option<Res<TowerKinds>>) -> bool {
+    tower_kinds.is_some()
+}
+
+/// The tower kind that will be built on the next click on a build spot.
+#[derive(Default)]
+pub struct SelectedTowerKind(pub usize);
+
+struct TowerKindListHandle(Handle<TowerKindList>);
+
+fn tower_kind_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(TowerKindListHandle(
+        asset_server.load("data/default.towers.ron"),
+    ));
+}
+
+/// Rebuilds [`TowerKinds`] whenever the tower kind file is (re)loaded.
+fn tower_kinds_update(
+    mut commands: Commands,
+    mut events: EventReader<AssetEvent<TowerKindList>>,
+    mut selected: ResMut<SelectedTowerKind>,
+    tower_kind_list_handle: Res<TowerKindListHandle>,
+    tower_kind_lists: Res<Assets<TowerKindList>>,
+) {
+    for event in events.iter() {
+        if let AssetEvent::Removed { .. } = event {
+            continue;
+        }
+        let kinds = match tower_kind_lists.get(&tower_kind_list_handle.0) {
+            Some(list) => list.kinds.clone(),
+            None => continue,
+        };
+
+        if selected.0 >= kinds.len() {
+            selected.0 = 0;
+        }
+        commands.insert_resource(TowerKinds(kinds));
+    }
+}
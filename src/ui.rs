@@ -3,15 +3,40 @@ use bevy_egui::{egui, EguiContext, EguiSettings};
 use iyes_loopless::prelude::*;
 
 use crate::{
-    audio::GlobalVolume, base::Base, currency::Currency, enemy::PlayTime, game_state::GameState,
+    audio::GlobalVolume,
+    base::Base,
+    clock::{GameClock, SPEEDS},
+    currency::Currency,
+    editor::{Editor, EditorAction, EditorMap, EditorTool},
+    game_state::GameState,
+    generator::GeneratorSettings,
     health::Health,
+    map::MapSource,
+    stats::GameStats,
+    targeting::TargetPriority,
+    tower::{SellRefund, SellTower, Tower, TowerUpgrades, UpgradeTower},
+    tower_input::Selection,
+    tower_kind::{SelectedTowerKind, TowerKinds, TowerStats},
+    wave::CurrentWave,
 };
 
 pub struct UiPlugin;
 
 impl Plugin for UiPlugin {
     fn build(&self, app: &mut App) {
-        app.add_startup_system(ui_setup).add_system(ui);
+        app.add_startup_system(ui_setup)
+            .add_system(
+                ui.run_not_in_state(GameState::MainMenu)
+                    .run_not_in_state(GameState::Editor),
+            )
+            .add_system(
+                build_panel
+                    .run_not_in_state(GameState::MainMenu)
+                    .run_not_in_state(GameState::Editor),
+            )
+            .add_system(tower_panel)
+            .add_system(game_summary)
+            .add_system(editor_panel.run_in_state(GameState::Editor));
     }
 }
 
@@ -30,7 +55,8 @@ fn ui(
     mut egui_ctx: ResMut<EguiContext>,
     mut volume: ResMut<GlobalVolume>,
     currency: Res<Currency>,
-    play_time: Res<PlayTime>,
+    mut clock: ResMut<GameClock>,
+    current_wave: Res<CurrentWave>,
     game_state: Res<CurrentState<GameState>>,
     base_query: Query<&Health, With<Base>>,
 ) {
@@ -49,24 +75,25 @@ fn ui(
                         base_health.current, base_health.max
                     ));
                 }
+
+                ui.separator();
+
+                ui.label(format!(
+                    "Wave: {}/{}",
+                    current_wave.number, current_wave.total
+                ));
             });
 
             ui.with_layout(egui::Layout::right_to_left(), |ui| {
-                let seconds = play_time.seconds.floor() as i32;
-                let clock = format!(
-                    "{:02}:{:02}:{:02}",
-                    seconds / 3600,
-                    seconds / 60,
-                    seconds % 60
-                );
+                let clock_text = format_clock(clock.elapsed());
                 if game_state.0 == GameState::Paused {
                     ui.scope(|ui| {
                         ui.visuals_mut().override_text_color =
                             Some(egui::Color32::from_rgb(255, 95, 0));
-                        ui.label(clock);
+                        ui.label(clock_text);
                     });
                 } else {
-                    ui.label(clock);
+                    ui.label(clock_text);
                 }
 
                 if game_state.0 == GameState::Playing {
@@ -74,11 +101,26 @@ fn ui(
                         commands.insert_resource(NextState(GameState::Paused));
                     }
                 } else if game_state.0 == GameState::Paused {
+                    if ui.add(egui::widgets::Button::new("⏭")).clicked() {
+                        clock.step();
+                    }
                     if ui.add(egui::widgets::Button::new("▶")).clicked() {
                         commands.insert_resource(NextState(GameState::Playing));
                     }
                 }
 
+                // Laid out right to left, so the fastest speed comes first.
+                for speed in SPEEDS.into_iter().rev() {
+                    let selected = clock.speed == speed;
+                    if ui
+                        .selectable_label(selected, format!("{}x", speed))
+                        .clicked()
+                        && !selected
+                    {
+                        clock.speed = speed;
+                    }
+                }
+
                 ui.separator();
 
                 let volume_icon = match volume.0 {
@@ -95,3 +137,314 @@ fn ui(
         });
     });
 }
+
+fn build_panel(
+    mut egui_ctx: ResMut<EguiContext>,
+    mut selected_kind: ResMut<SelectedTowerKind>,
+    currency: Res<Currency>,
+    tower_kinds: Option<Res<TowerKinds>>,
+) {
+    let tower_kinds = match tower_kinds {
+        Some(tower_kinds) => tower_kinds,
+        None => return,
+    };
+
+    egui::TopBottomPanel::bottom("bottom_panel").show(egui_ctx.ctx_mut(), |ui| {
+        ui.horizontal(|ui| {
+            ui.set_min_height(24.0);
+
+            for (i, kind) in tower_kinds.iter().enumerate() {
+                let mut text =
+                    egui::RichText::new(format!("{} {} ({})", i + 1, kind.name, kind.cost));
+                if currency.coins < kind.cost {
+                    text = text.weak();
+                }
+                if ui.selectable_label(selected_kind.0 == i, text).clicked() {
+                    selected_kind.0 = i;
+                }
+            }
+        });
+    });
+}
+
+fn tower_panel(
+    mut egui_ctx: ResMut<EguiContext>,
+    mut upgrade_events: EventWriter<UpgradeTower>,
+    mut sell_events: EventWriter<SellTower>,
+    mut sell_pending: Local<Option<Entity>>,
+    currency: Res<Currency>,
+    refund: Res<SellRefund>,
+    selection: Res<Option<Selection>>,
+    game_state: Res<CurrentState<GameState>>,
+    tower_kinds: Option<Res<TowerKinds>>,
+    mut tower_query: Query<(&mut Tower, &TowerStats, &TowerUpgrades)>,
+) {
+    if game_state.0 != GameState::Playing {
+        return;
+    }
+    let (tower_kinds, selection) = match (tower_kinds, &*selection) {
+        (Some(tower_kinds), Some(selection)) => (tower_kinds, selection),
+        _ => return,
+    };
+    let (mut tower, stats, upgrades) = match tower_query.get_mut(selection.0) {
+        Ok(tower) => tower,
+        Err(_) => return,
+    };
+    let kind = &tower_kinds[tower.kind];
+
+    egui::Window::new(&kind.name)
+        .anchor(egui::Align2::RIGHT_TOP, egui::vec2(-8.0, 48.0))
+        .collapsible(false)
+        .resizable(false)
+        .show(egui_ctx.ctx_mut(), |ui| {
+            egui::Grid::new("tower_stats_grid").show(ui, |ui| {
+                ui.label("Range");
+                ui.label(format!("{:.0}", stats.range));
+                ui.end_row();
+
+                ui.label("Fire rate");
+                ui.label(format!("{:.1}/s", stats.fire_rate));
+                ui.end_row();
+
+                ui.label("Damage");
+                ui.label(stats.damage.to_string());
+                ui.end_row();
+            });
+
+            ui.separator();
+
+            let mut priority = tower.priority;
+            egui::ComboBox::from_label("Target")
+                .selected_text(priority.name())
+                .show_ui(ui, |ui| {
+                    for option in TargetPriority::ALL {
+                        ui.selectable_value(&mut priority, option, option.name());
+                    }
+                });
+            if priority != tower.priority {
+                tower.priority = priority;
+            }
+
+            for (i, branch) in kind.upgrades.iter().enumerate() {
+                if i == 0 {
+                    ui.separator();
+                }
+
+                let level = match upgrades.branch {
+                    Some(current) if current == i => upgrades.level,
+                    Some(_) => continue,
+                    None => 0,
+                };
+                let label = format!("{} {}/{}", branch.name, level, branch.levels.len());
+
+                match upgrades.next(kind, i) {
+                    Some(upgrade) => {
+                        let button = egui::Button::new(format!("{} ({})", label, upgrade.cost));
+                        if ui
+                            .add_enabled(currency.coins >= upgrade.cost, button)
+                            .clicked()
+                        {
+                            upgrade_events.send(UpgradeTower {
+                                tower: selection.0,
+                                branch: i,
+                            });
+                        }
+                    }
+                    None => {
+                        ui.label(label);
+                    }
+                }
+            }
+
+            ui.separator();
+
+            // Expensive towers need a second click, so they can't be sold by
+            // accident.
+            let sell_value = tower.sell_value(*refund);
+            if *sell_pending == Some(selection.0) {
+                ui.horizontal(|ui| {
+                    if ui
+                        .button(format!("Confirm sell (+{})", sell_value))
+                        .clicked()
+                    {
+                        sell_events.send(SellTower { tower: selection.0 });
+                        *sell_pending = None;
+                    }
+                    if ui.button("Cancel").clicked() {
+                        *sell_pending = None;
+                    }
+                });
+            } else if ui.button(format!("Sell (+{})", sell_value)).clicked() {
+                if tower.invested >= SELL_CONFIRM_INVESTMENT {
+                    *sell_pending = Some(selection.0);
+                } else {
+                    sell_events.send(SellTower { tower: selection.0 });
+                }
+            }
+        });
+}
+
+/// Towers with at least this many coins invested ask for confirmation before
+/// being sold.
+const SELL_CONFIRM_INVESTMENT: i32 = 15;
+
+fn format_clock(seconds: f64) -> String {
+    let seconds = seconds.floor() as i32;
+    format!(
+        "{:02}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+fn game_summary(
+    mut commands: Commands,
+    mut egui_ctx: ResMut<EguiContext>,
+    mut source: ResMut<MapSource>,
+    stats: Res<GameStats>,
+    clock: Res<GameClock>,
+    game_state: Res<CurrentState<GameState>>,
+    base_query: Query<&Health, With<Base>>,
+) {
+    let title = match game_state.0 {
+        GameState::Victory => "Victory",
+        GameState::GameOver => "Game Over",
+        _ => return,
+    };
+
+    egui::Window::new(title)
+        .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+        .collapsible(false)
+        .resizable(false)
+        .show(egui_ctx.ctx_mut(), |ui| {
+            egui::Grid::new("summary_grid").show(ui, |ui| {
+                ui.label("Time");
+                ui.label(format_clock(clock.elapsed()));
+                ui.end_row();
+
+                ui.label("Kills");
+                ui.label(stats.kills.to_string());
+                ui.end_row();
+
+                ui.label("Coins earned");
+                ui.label(stats.coins_earned.to_string());
+                ui.end_row();
+
+                ui.label("Base health");
+                let base_health = base_query.get_single().map_or(0, |health| health.current);
+                ui.label(base_health.max(0).to_string());
+                ui.end_row();
+            });
+
+            ui.separator();
+
+            ui.horizontal(|ui| {
+                if ui.button("Retry").clicked() {
+                    commands.insert_resource(NextState(GameState::LoadingMap));
+                }
+                if ui.button("Back to menu").clicked() {
+                    commands.insert_resource(NextState(GameState::MainMenu));
+                }
+                if ui.button("Random map").clicked() {
+                    let difficulty = match &*source {
+                        MapSource::Generated(settings) => settings.difficulty,
+                        MapSource::File(_) | MapSource::Asset(_) => default(),
+                    };
+                    *source = MapSource::Generated(GeneratorSettings {
+                        seed: rand::random(),
+                        difficulty,
+                        ..default()
+                    });
+                    commands.insert_resource(NextState(GameState::LoadingMap));
+                }
+            });
+        });
+}
+
+fn editor_panel(
+    mut egui_ctx: ResMut<EguiContext>,
+    mut editor: ResMut<Editor>,
+    mut editor_map: ResMut<EditorMap>,
+    mut events: EventWriter<EditorAction>,
+) {
+    egui::SidePanel::left("editor_panel").show(egui_ctx.ctx_mut(), |ui| {
+        ui.heading("Map editor");
+
+        // Only write back on change, so the map is not redrawn every frame.
+        let mut name = editor_map.0.name.clone();
+        let mut starting_coins = editor_map.0.starting_coins;
+        egui::Grid::new("editor_map_grid").show(ui, |ui| {
+            ui.label("Name");
+            ui.text_edit_singleline(&mut name);
+            ui.end_row();
+
+            ui.label("Coins");
+            ui.add(egui::DragValue::new(&mut starting_coins).clamp_range(0..=1000));
+            ui.end_row();
+        });
+        if name != editor_map.0.name {
+            editor_map.0.name = name;
+        }
+        if starting_coins != editor_map.0.starting_coins {
+            editor_map.0.starting_coins = starting_coins;
+        }
+
+        ui.separator();
+
+        let mut tool = editor.tool;
+        for option in EditorTool::ALL {
+            ui.radio_value(&mut tool, option, option.name());
+        }
+        if tool != editor.tool {
+            editor.tool = tool;
+        }
+
+        let lanes = editor_map.0.lanes.len();
+        if lanes > 0 {
+            let mut lane = editor.lane;
+            egui::ComboBox::from_label("Lane")
+                .selected_text(format!("Lane {}", lane + 1))
+                .show_ui(ui, |ui| {
+                    for i in 0..lanes {
+                        ui.selectable_value(&mut lane, i, format!("Lane {}", i + 1));
+                    }
+                });
+            if lane != editor.lane {
+                editor.lane = lane;
+            }
+        }
+
+        ui.separator();
+
+        if editor.problems.is_empty() {
+            ui.label("No problems");
+        } else {
+            for problem in &editor.problems {
+                ui.colored_label(egui::Color32::from_rgb(200, 30, 30), problem.to_string());
+            }
+        }
+
+        ui.separator();
+
+        ui.horizontal(|ui| {
+            if ui.button("Save").clicked() {
+                events.send(EditorAction::Save);
+            }
+            if ui.button("Play-test (F5)").clicked() {
+                events.send(EditorAction::PlayTest);
+            }
+        });
+        if ui.button("Discard (F2)").clicked() {
+            events.send(EditorAction::Discard);
+        }
+        if let Some(message) = &editor.message {
+            ui.label(message);
+        }
+
+        ui.separator();
+
+        ui.weak("Left click to add, right click to remove.");
+        ui.weak("The path tool extends the selected lane.");
+    });
+}
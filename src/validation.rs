@@ -0,0 +1,326 @@
+use std::{collections::HashSet, fmt};
+
+use crate::{coord::Coord, field::OpenField, map::Map, tile_map::path_cells};
+
+/// Something that makes a [`Map`] unplayable.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MapProblem {
+    NoLanes,
+    /// Selling a tower would pay back less than nothing or more than it cost.
+    SellRefundOutOfRange,
+    /// The lane has no path, or a path without a single segment.
+    EmptyPath {
+        lane: usize,
+    },
+    /// Paths may only run horizontally or vertically.
+    DiagonalSegment {
+        lane: usize,
+        from: Coord,
+        to: Coord,
+    },
+    /// The same node comes up twice in one path or branch.
+    DuplicateNode {
+        lane: usize,
+        position: Coord,
+    },
+    /// The branch does not start on its lane's path.
+    BranchOffPath {
+        lane: usize,
+        branch: usize,
+        start: Coord,
+    },
+    /// Enemies of the lane get stuck at `position` instead of reaching the
+    /// base.
+    BaseUnreachable {
+        lane: usize,
+        position: Coord,
+    },
+    BuildSpotOnPath(Coord),
+}
+
+impl MapProblem {
+    /// Where on the map the problem is, if anywhere in particular.
+    pub fn position(&self) -> Option<Coord> {
+        match *self {
+            MapProblem::NoLanes
+            | MapProblem::SellRefundOutOfRange
+            | MapProblem::EmptyPath { .. } => None,
+            MapProblem::DiagonalSegment { from, .. } => Some(from),
+            MapProblem::DuplicateNode { position, .. } => Some(position),
+            MapProblem::BranchOffPath { start, .. } => Some(start),
+            MapProblem::BaseUnreachable { position, .. } => Some(position),
+            MapProblem::BuildSpotOnPath(position) => Some(position),
+        }
+    }
+}
+
+impl fmt::Display for MapProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MapProblem::NoLanes => write!(f, "The map has no spawner"),
+            MapProblem::SellRefundOutOfRange => {
+                write!(f, "The sell refund must be between 0 and 1")
+            }
+            MapProblem::EmptyPath { lane } => write!(f, "Lane {} has an empty path", lane + 1),
+            MapProblem::DiagonalSegment { lane, from, to } => write!(
+                f,
+                "Lane {} runs diagonally from {} to {}",
+                lane + 1,
+                from,
+                to
+            ),
+            MapProblem::DuplicateNode { lane, position } => {
+                write!(f, "Lane {} passes {} twice", lane + 1, position)
+            }
+            MapProblem::BranchOffPath {
+                lane,
+                branch,
+                start,
+            } => write!(
+                f,
+                "Branch {} of lane {} starts off the path at {}",
+                branch + 1,
+                lane + 1,
+                start
+            ),
+            MapProblem::BaseUnreachable { lane, position } => write!(
+                f,
+                "Lane {} cannot reach the base from {}",
+                lane + 1,
+                position
+            ),
+            MapProblem::BuildSpotOnPath(position) => {
+                write!(f, "Build spot on the path at {}", position)
+            }
+        }
+    }
+}
+
+/// Every problem with `map`, in the order of the map file.
+pub fn validate_map(map: &Map) -> Vec<MapProblem> {
+    let mut problems = Vec::new();
+    if map.lanes.is_empty() {
+        problems.push(MapProblem::NoLanes);
+    }
+    if !(0.0..=1.0).contains(&map.sell_refund.0) {
+        problems.push(MapProblem::SellRefundOutOfRange);
+    }
+
+    // Enemies find their own way in open fields, so the path is only the
+    // entrance there.
+    let field = map.open_field.map(|_| {
+        let field = OpenField {
+            base: map.base,
+            entrances: Vec::new(),
+        };
+        (field, map.tile_map())
+    });
+
+    let mut path_cells_seen = HashSet::new();
+    for (lane_index, lane) in map.lanes.iter().enumerate() {
+        let min_len = if field.is_some() { 1 } else { 2 };
+        if lane.path.len() < min_len {
+            problems.push(MapProblem::EmptyPath { lane: lane_index });
+            continue;
+        }
+        check_nodes(lane_index, &lane.path, &mut problems);
+        path_cells_seen.extend(path_cells(&lane.path));
+
+        if let Some((field, tile_map)) = &field {
+            let entrance = lane.path[0];
+            if field.route(tile_map, entrance, &HashSet::new()).is_none() {
+                problems.push(MapProblem::BaseUnreachable {
+                    lane: lane_index,
+                    position: entrance,
+                });
+            }
+            continue;
+        }
+
+        let end = *lane.path.last().unwrap();
+        if end != map.base {
+            problems.push(MapProblem::BaseUnreachable {
+                lane: lane_index,
+                position: end,
+            });
+        }
+
+        // Branches may also fork off and merge into earlier branches.
+        let mut nodes: HashSet<Coord> = lane.path.iter().copied().collect();
+        for (branch_index, branch) in lane.branches.iter().enumerate() {
+            let (start, end) = match (branch.path.first(), branch.path.last()) {
+                (Some(&start), Some(&end)) => (start, end),
+                _ => continue,
+            };
+            if !nodes.contains(&start) {
+                problems.push(MapProblem::BranchOffPath {
+                    lane: lane_index,
+                    branch: branch_index,
+                    start,
+                });
+            } else if !nodes.contains(&end) && end != map.base {
+                problems.push(MapProblem::BaseUnreachable {
+                    lane: lane_index,
+                    position: end,
+                });
+            }
+            check_nodes(lane_index, &branch.path, &mut problems);
+            nodes.extend(&branch.path);
+            path_cells_seen.extend(path_cells(&branch.path));
+        }
+    }
+
+    for &build_spot in &map.build_spots {
+        if path_cells_seen.contains(&build_spot) {
+            problems.push(MapProblem::BuildSpotOnPath(build_spot));
+        }
+    }
+    problems
+}
+
+/// Checks the segments and nodes of a single path or branch.
+fn check_nodes(lane: usize, nodes: &[Coord], problems: &mut Vec<MapProblem>) {
+    for segment in nodes.windows(2) {
+        let (from, to) = (segment[0], segment[1]);
+        if from.x != to.x && from.y != to.y {
+            problems.push(MapProblem::DiagonalSegment { lane, from, to });
+        }
+    }
+    let mut seen = HashSet::new();
+    for &node in nodes {
+        if !seen.insert(node) {
+            problems.push(MapProblem::DuplicateNode {
+                lane,
+                position: node,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        map::{Branch, Lane},
+        path::BranchChoice,
+        tile_map::Bounds,
+        tower::SellRefund,
+    };
+
+    fn lane(path: &[(i32, i32)]) -> Lane {
+        Lane {
+            path: path.iter().copied().map(Coord::from).collect(),
+            branches: Vec::new(),
+            choice: BranchChoice::default(),
+            waves: Vec::new(),
+        }
+    }
+
+    fn map(lanes: Vec<Lane>, build_spots: &[(i32, i32)]) -> Map {
+        Map {
+            name: "Test".to_string(),
+            difficulty: Default::default(),
+            starting_coins: 10,
+            sell_refund: Default::default(),
+            base: Coord::new(4, 0),
+            lanes,
+            build_spots: build_spots.iter().copied().map(Coord::from).collect(),
+            open_field: None,
+            terrain: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn valid_map_has_no_problems() {
+        let map = map(vec![lane(&[(0, 0), (0, 2), (4, 2), (4, 0)])], &[(1, 1)]);
+        assert_eq!(validate_map(&map), []);
+    }
+
+    #[test]
+    fn reports_every_problem_with_its_position() {
+        let mut first = lane(&[(0, 0), (2, 2), (2, 0), (2, 2), (3, 2)]);
+        first.branches.push(Branch {
+            path: vec![Coord::new(9, 9), Coord::new(9, 0)],
+            weight: 1.0,
+        });
+        let map = map(vec![first, lane(&[]), lane(&[(0, 0)])], &[(2, 1), (5, 5)]);
+
+        assert_eq!(
+            validate_map(&map),
+            [
+                MapProblem::DiagonalSegment {
+                    lane: 0,
+                    from: Coord::new(0, 0),
+                    to: Coord::new(2, 2),
+                },
+                MapProblem::DuplicateNode {
+                    lane: 0,
+                    position: Coord::new(2, 2),
+                },
+                MapProblem::BaseUnreachable {
+                    lane: 0,
+                    position: Coord::new(3, 2),
+                },
+                MapProblem::BranchOffPath {
+                    lane: 0,
+                    branch: 0,
+                    start: Coord::new(9, 9),
+                },
+                MapProblem::EmptyPath { lane: 1 },
+                MapProblem::EmptyPath { lane: 2 },
+                MapProblem::BuildSpotOnPath(Coord::new(2, 1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn sell_refund_must_be_a_fraction() {
+        let mut map = map(vec![lane(&[(0, 0), (4, 0)])], &[]);
+        map.sell_refund = SellRefund(1.0);
+        assert_eq!(validate_map(&map), []);
+
+        map.sell_refund = SellRefund(1.5);
+        assert_eq!(validate_map(&map), [MapProblem::SellRefundOutOfRange]);
+        map.sell_refund = SellRefund(-0.5);
+        assert_eq!(validate_map(&map), [MapProblem::SellRefundOutOfRange]);
+    }
+
+    #[test]
+    fn branches_must_lead_back_to_the_path() {
+        let mut lane = lane(&[(0, 0), (4, 0)]);
+        lane.branches.push(Branch {
+            path: vec![Coord::new(0, 0), Coord::new(0, 2), Coord::new(2, 2)],
+            weight: 1.0,
+        });
+        let map = map(vec![lane], &[]);
+
+        assert_eq!(
+            validate_map(&map),
+            [MapProblem::BaseUnreachable {
+                lane: 0,
+                position: Coord::new(2, 2),
+            }]
+        );
+    }
+
+    #[test]
+    fn open_field_base_must_be_reachable() {
+        let mut map = map(vec![lane(&[(0, 0)])], &[]);
+        map.open_field = Some(Bounds {
+            min: Coord::new(0, 0),
+            max: Coord::new(2, 2),
+        });
+        map.base = Coord::new(3, 0);
+        assert_eq!(validate_map(&map), []);
+
+        // Past the blocked margin around the field.
+        map.base = Coord::new(5, 0);
+        assert_eq!(
+            validate_map(&map),
+            [MapProblem::BaseUnreachable {
+                lane: 0,
+                position: Coord::new(0, 0),
+            }]
+        );
+    }
+}
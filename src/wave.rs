@@ -0,0 +1,72 @@
+use bevy::prelude::*;
+use iyes_loopless::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use std::num::NonZeroU32;
+
+use crate::{
+    base::Base,
+    enemy::{Enemy, EnemySpawner},
+    game_state::GameState,
+    health::Health,
+    simulation::{SimulationAppExt, SimulationPhase},
+};
+
+pub struct WavePlugin;
+
+impl Plugin for WavePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<WavesCleared>()
+            .init_resource::<CurrentWave>()
+            .add_simulation_system(SimulationPhase::Cleanup, wave_clear);
+    }
+}
+
+/// A group of identical enemies sent by a spawner.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Wave {
+    /// Name of the enemy kind to spawn.
+    pub enemy: String,
+    pub count: NonZeroU32,
+    /// Seconds between two enemies of this wave.
+    pub spacing: f64,
+    /// Seconds between the last enemy of this wave and the start of the next
+    /// wave.
+    pub delay: f64,
+}
+
+#[derive(Default)]
+pub struct CurrentWave {
+    /// 1-based number of the wave being spawned, 0 before the first one.
+    pub number: usize,
+    pub total: usize,
+    pub cleared: bool,
+}
+
+/// Sent once when every wave has been spawned and no enemy is left alive.
+pub struct WavesCleared;
+
+fn wave_clear(
+    mut commands: Commands,
+    mut current_wave: ResMut<CurrentWave>,
+    mut events: EventWriter<WavesCleared>,
+    spawner_query: Query<&EnemySpawner>,
+    enemy_query: Query<(), With<Enemy>>,
+    base_query: Query<&Health, With<Base>>,
+) {
+    if current_wave.cleared {
+        return;
+    }
+
+    // Spawners are created through events, so there might not be any yet.
+    if spawner_query.is_empty() || !spawner_query.iter().all(EnemySpawner::is_done) {
+        return;
+    }
+
+    // The last enemy might have taken the base down with it.
+    if enemy_query.is_empty() && base_query.iter().all(|health| health.current > 0) {
+        current_wave.cleared = true;
+        events.send(WavesCleared);
+        commands.insert_resource(NextState(GameState::Victory));
+    }
+}
@@ -0,0 +1,269 @@
+use bevy::{
+    ecs::{event::Events, system::Resource},
+    prelude::*,
+};
+use iyes_loopless::prelude::*;
+
+use std::time::{Duration, Instant};
+
+use tower_defense::{
+    base::Base,
+    clock::TICK_SECONDS,
+    enemy::Enemy,
+    game_state::GameState,
+    headless::{headless_app, run_scenario, BuildStep, Scenario},
+    map::Map,
+    stats::GameStats,
+    tower::{SellRefund, SellTower, SpawnTower, Tower, TowerUpgrades, UpgradeTower},
+    tower_kind::{TowerKinds, TowerStats},
+    Coord, Currency, Health,
+};
+
+/// A straight lane of four cells with one build spot beside it, sending a
+/// single enemy of `enemy` kind.
+fn corridor(enemy: &str) -> Map {
+    ron::from_str(&format!(
+        r#"(
+            name: "Corridor",
+            starting_coins: 5,
+            base: (0, 0),
+            lanes: [(
+                path: [(-4, 0), (0, 0)],
+                waves: [(enemy: "{}", count: 1, spacing: 1.0, delay: 0.0)],
+            )],
+            build_spots: [(-2, 1)],
+        )"#,
+        enemy
+    ))
+    .unwrap()
+}
+
+/// Sets up `map` and returns once it is being played.
+fn start(map: Map) -> App {
+    let mut app = headless_app(map);
+    let started = Instant::now();
+    while app.world.resource::<CurrentState<GameState>>().0 != GameState::Playing {
+        assert!(
+            started.elapsed() < Duration::from_secs(10),
+            "Map was not set up"
+        );
+        app.update();
+    }
+    app
+}
+
+fn run_seconds(app: &mut App, seconds: f64) {
+    for _ in 0..(seconds / TICK_SECONDS) as u32 {
+        app.update();
+    }
+}
+
+fn base_health(app: &mut App) -> i32 {
+    app.world
+        .query_filtered::<&Health, With<Base>>()
+        .iter(&app.world)
+        .next()
+        .unwrap()
+        .current
+}
+
+/// Sends `event` like the UI would, and runs the update that handles it.
+fn send<E: Resource>(app: &mut App, event: E) {
+    app.world.resource_mut::<Events<E>>().send(event);
+    app.update();
+}
+
+fn build(app: &mut App, tower: &str, position: Coord) {
+    let kind = app.world.resource::<TowerKinds>().index_of(tower).unwrap();
+    send(app, SpawnTower { position, kind });
+}
+
+/// The only tower on the map.
+fn tower(app: &mut App) -> Entity {
+    app.world
+        .query_filtered::<Entity, With<Tower>>()
+        .iter(&app.world)
+        .next()
+        .unwrap()
+}
+
+fn coins(app: &App) -> i32 {
+    app.world.resource::<Currency>().coins
+}
+
+#[test]
+fn map_is_set_up() {
+    let mut app = start(corridor("grunt"));
+
+    assert_eq!(base_health(&mut app), 20);
+    assert_eq!(coins(&app), 5);
+}
+
+#[test]
+fn enemy_reaching_base_damages_it() {
+    let mut app = start(corridor("grunt"));
+
+    run_seconds(&mut app, 20.0);
+
+    assert_eq!(base_health(&mut app), 19);
+    assert_eq!(app.world.query::<&Enemy>().iter(&app.world).count(), 0);
+}
+
+#[test]
+fn tower_costs_coins() {
+    let mut app = start(corridor("grunt"));
+
+    build(&mut app, "Gun", Coord::new(-2, 1));
+
+    assert_eq!(app.world.query::<&Tower>().iter(&app.world).count(), 1);
+    assert_eq!(coins(&app), 0);
+}
+
+#[test]
+fn tower_off_build_spot_is_refused() {
+    let mut app = start(corridor("grunt"));
+
+    build(&mut app, "Gun", Coord::new(-2, 0));
+
+    assert_eq!(app.world.query::<&Tower>().iter(&app.world).count(), 0);
+    assert_eq!(coins(&app), 5);
+}
+
+#[test]
+fn tower_kill_earns_bounty() {
+    let mut app = start(corridor("swarmling"));
+    build(&mut app, "Gun", Coord::new(-2, 1));
+
+    run_seconds(&mut app, 20.0);
+
+    assert_eq!(base_health(&mut app), 20);
+    assert_eq!(app.world.resource::<GameStats>().kills, 1);
+    assert_eq!(coins(&app), 1);
+    let kills: Vec<u32> = app
+        .world
+        .query::<&Tower>()
+        .iter(&app.world)
+        .map(|tower| tower.kills)
+        .collect();
+    assert_eq!(kills, [1]);
+}
+
+#[test]
+fn clearing_all_waves_wins() {
+    let mut app = start(corridor("swarmling"));
+    build(&mut app, "Gun", Coord::new(-2, 1));
+
+    run_seconds(&mut app, 20.0);
+
+    assert_eq!(
+        app.world.resource::<CurrentState<GameState>>().0,
+        GameState::Victory
+    );
+}
+
+#[test]
+fn upgrades_lock_in_a_branch_up_to_its_last_level() {
+    let mut app = start(corridor("grunt"));
+    app.world.resource_mut::<Currency>().coins = 100;
+    build(&mut app, "Gun", Coord::new(-2, 1));
+    let tower = tower(&mut app);
+    let tower_kinds = app.world.resource::<TowerKinds>();
+    let gun = &tower_kinds[tower_kinds.index_of("Gun").unwrap()];
+    let base_range = gun.stats.range;
+    let levels: Vec<(i32, f32)> = gun.upgrades[1]
+        .levels
+        .iter()
+        .map(|level| (level.cost, level.stats.range))
+        .collect();
+    let range = |app: &App| app.world.get::<TowerStats>(tower).unwrap().range;
+    let upgrades = |app: &App| {
+        let upgrades = app.world.get::<TowerUpgrades>(tower).unwrap();
+        (upgrades.branch, upgrades.level)
+    };
+
+    let before = coins(&app);
+    send(&mut app, UpgradeTower { tower, branch: 1 });
+    assert_eq!(coins(&app), before - levels[0].0);
+    assert_eq!(range(&app), base_range + levels[0].1);
+    assert_eq!(upgrades(&app), (Some(1), 1));
+
+    let before = coins(&app);
+    send(&mut app, UpgradeTower { tower, branch: 0 });
+    assert_eq!(coins(&app), before);
+    assert_eq!(upgrades(&app), (Some(1), 1));
+
+    for _ in 1..levels.len() {
+        send(&mut app, UpgradeTower { tower, branch: 1 });
+    }
+    let before = coins(&app);
+    send(&mut app, UpgradeTower { tower, branch: 1 });
+    assert_eq!(coins(&app), before);
+    assert_eq!(upgrades(&app), (Some(1), levels.len()));
+    let total_range: f32 = levels.iter().map(|&(_, range)| range).sum();
+    assert_eq!(range(&app), base_range + total_range);
+}
+
+#[test]
+fn selling_refunds_upgrades_and_frees_the_cell() {
+    let mut app = start(corridor("grunt"));
+    app.world.resource_mut::<Currency>().coins = 100;
+    build(&mut app, "Gun", Coord::new(-2, 1));
+    let tower = tower(&mut app);
+    send(&mut app, UpgradeTower { tower, branch: 0 });
+    let invested = 100 - coins(&app);
+    let tower_kinds = app.world.resource::<TowerKinds>();
+    let build_cost = tower_kinds[tower_kinds.index_of("Gun").unwrap()].cost;
+    assert!(invested > build_cost);
+
+    send(&mut app, SellTower { tower });
+
+    let refund = *app.world.resource::<SellRefund>();
+    let refunded = coins(&app) - (100 - invested);
+    assert_eq!(refunded, (invested as f32 * refund.0).floor() as i32);
+    assert!(refunded > (build_cost as f32 * refund.0).floor() as i32);
+    assert_eq!(app.world.query::<&Tower>().iter(&app.world).count(), 0);
+
+    build(&mut app, "Gun", Coord::new(-2, 1));
+    assert_eq!(app.world.query::<&Tower>().iter(&app.world).count(), 1);
+}
+
+/// Guns built one by one on a map where enemies pick between routes.
+fn crossroads() -> Scenario {
+    let build = |time, at| BuildStep::Build {
+        time,
+        tower: "Gun".to_string(),
+        at,
+    };
+    Scenario {
+        map: "maps/level2.map.ron".to_string(),
+        waves: None,
+        builds: vec![
+            build(0.0, Coord::new(-4, 1)),
+            build(0.0, Coord::new(1, 1)),
+            build(10.0, Coord::new(-3, 1)),
+            BuildStep::Upgrade {
+                time: 20.0,
+                at: Coord::new(1, 1),
+                branch: 1,
+            },
+        ],
+        time_limit: 120.0,
+    }
+}
+
+#[test]
+fn same_scenario_plays_out_the_same() {
+    let first = run_scenario(&crossroads(), 1).unwrap();
+    let second = run_scenario(&crossroads(), 1).unwrap();
+
+    assert!(first.kills > 0);
+    assert_eq!(first, second);
+}
+
+#[test]
+fn ticks_per_update_do_not_change_the_outcome() {
+    let one = run_scenario(&crossroads(), 1).unwrap();
+    let several = run_scenario(&crossroads(), 7).unwrap();
+
+    assert_eq!(one, several);
+}